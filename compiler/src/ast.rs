@@ -1,7 +1,9 @@
 use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
 
 #[derive(Debug)]
-pub struct AST(pub Vec<NamedExpr>, pub ExprRef);
+pub struct AST(pub Vec<NamedExpr>, pub ExprRef, pub Vec<Signature>);
 
 #[derive(Debug)]
 pub struct NamedExpr {
@@ -9,6 +11,14 @@ pub struct NamedExpr {
     pub expr_ref: ExprRef,
 }
 
+/// The shape of an input, e.g. `x: [128, _]`, with `None` for a dimension only known at run
+/// time. Signatures apply to the program's inputs in order.
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub ident: Symbol,
+    pub shape: Vec<Option<usize>>,
+}
+
 #[derive(Clone, Debug)]
 pub enum Expr {
     Index(IndexExpr),
@@ -27,6 +37,7 @@ pub struct ExprRef(pub usize);
 pub struct IndexExpr {
     pub op: ScalarOp,
     pub out: Symbol,
+    pub scan: Option<char>, // output index marked `\` to accumulate along, e.g. `+ij~i\j`
     pub schedule: Schedule,
 }
 
@@ -43,29 +54,178 @@ pub struct Schedule {
 
 #[derive(Clone, Debug)]
 pub enum ScalarOp {
-    BinaryOp(BinaryOp),
-    UnaryOp(UnaryOp),
+    TernaryOp(Op, Symbol, Symbol, Symbol),
+    BinaryOp(Op, Symbol, Symbol),
+    UnaryOp(Op, Symbol),
     NoOp(NoOp),
 }
 
-#[derive(Clone, Debug)]
-pub enum BinaryOp {
-    Mul(Symbol, Symbol),
-    Div(Symbol, Symbol),
-    Add(Symbol, Symbol),
-    Sub(Symbol, Symbol),
-    Max(Symbol, Symbol),
+/// A scalar operation. Some ops mean different things by their number of inputs: with one input,
+/// `Sub` negates and `Div` takes the reciprocal, `Add` and `Mul` reduce, and `Max` and `Min`
+/// reduce over reduced indices or otherwise compare against zero (`Max` being relu). `Select`
+/// picks its second or third input by its first, `where(mask, a, b)`. `ArgMax` and `ArgMin`
+/// reduce to the (row-major, over the reduced indices) position of the extreme element.
+/// `Gather` reads its first input at the positions in its second, `x[idx]`, along the index of
+/// the first found in neither the second nor the output. `Scatter` adds its third input (or 1)
+/// to its first at the positions in its second, along the index of the output found in neither.
+/// Positions outside the indexed extent are skipped: a gather reads 0 there and a scatter adds
+/// nothing. `Concat` joins its inputs along the index of the output found in neither, from the index of
+/// each found only in it. `Zeros` and `Ones` fill the extents of their inputs without reading
+/// them.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Op {
+    Id,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Max,
+    Min,
+    Pow,
+    Exp,
+    Log,
+    Abs,
+    Sqrt,
+    Rsqrt,
+    Tanh,
+    Sigmoid,
+    Sin,
+    Cos,
+    Floor,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Select,
+    ArgMax,
+    ArgMin,
+    Gather,
+    Scatter,
+    Concat,
+    Zeros,
+    Ones,
 }
 
-#[derive(Clone, Debug)]
-pub enum UnaryOp {
-    Prod(Symbol),
-    Accum(Symbol),
-    Relu(Symbol),
-    Neg(Symbol),
-    Exp(Symbol),
-    Log(Symbol),
-    Recip(Symbol),
+impl Op {
+    /// The numbers of inputs the op accepts
+    pub fn arities(&self) -> &'static [usize] {
+        match self {
+            Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Max | Op::Min => &[1, 2],
+            Op::Pow | Op::Lt | Op::Le | Op::Gt | Op::Ge | Op::Eq | Op::Ne => &[2],
+            Op::Gather | Op::Concat => &[2],
+            Op::Zeros | Op::Ones => &[1, 2],
+            Op::Scatter => &[2, 3],
+            Op::Select => &[3],
+            _ => &[1],
+        }
+    }
+
+    /// Whether the op produces a `DType::Bool`
+    pub fn is_comparison(&self) -> bool {
+        matches!(self, Op::Lt | Op::Le | Op::Gt | Op::Ge | Op::Eq | Op::Ne)
+    }
+
+    /// The input whose (integer) elements are positions in another, for data-dependent indexing
+    pub fn position_input(&self) -> Option<usize> {
+        match self {
+            Op::Gather | Op::Scatter => Some(1),
+            _ => None,
+        }
+    }
+
+    /// The constant the op fills its output with, if it doesn't read its inputs
+    pub fn fill(&self) -> Option<f64> {
+        match self {
+            Op::Zeros => Some(0.),
+            Op::Ones => Some(1.),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Op::Id => "id",
+            Op::Add => "+",
+            Op::Sub => "-",
+            Op::Mul => "*",
+            Op::Div => "/",
+            Op::Max => ">",
+            Op::Min => "<",
+            Op::Pow => "pow",
+            Op::Exp => "exp",
+            Op::Log => "log",
+            Op::Abs => "abs",
+            Op::Sqrt => "sqrt",
+            Op::Rsqrt => "rsqrt",
+            Op::Tanh => "tanh",
+            Op::Sigmoid => "sigmoid",
+            Op::Sin => "sin",
+            Op::Cos => "cos",
+            Op::Floor => "floor",
+            Op::Lt => "lt",
+            Op::Le => "le",
+            Op::Gt => "gt",
+            Op::Ge => "ge",
+            Op::Eq => "eq",
+            Op::Ne => "ne",
+            Op::Select => "where",
+            Op::ArgMax => "argmax",
+            Op::ArgMin => "argmin",
+            Op::Gather => "gather",
+            Op::Scatter => "scatter",
+            Op::Concat => "concat",
+            Op::Zeros => "zeros",
+            Op::Ones => "ones",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    /// Parse the `Display` form of an op, or the names `max` and `min`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "id" => Ok(Op::Id),
+            "+" => Ok(Op::Add),
+            "-" => Ok(Op::Sub),
+            "*" => Ok(Op::Mul),
+            "/" => Ok(Op::Div),
+            ">" | "max" => Ok(Op::Max),
+            "<" | "min" => Ok(Op::Min),
+            "pow" => Ok(Op::Pow),
+            "exp" => Ok(Op::Exp),
+            "log" => Ok(Op::Log),
+            "abs" => Ok(Op::Abs),
+            "sqrt" => Ok(Op::Sqrt),
+            "rsqrt" => Ok(Op::Rsqrt),
+            "tanh" => Ok(Op::Tanh),
+            "sigmoid" => Ok(Op::Sigmoid),
+            "sin" => Ok(Op::Sin),
+            "cos" => Ok(Op::Cos),
+            "floor" => Ok(Op::Floor),
+            "lt" => Ok(Op::Lt),
+            "le" => Ok(Op::Le),
+            "gt" => Ok(Op::Gt),
+            "ge" => Ok(Op::Ge),
+            "eq" => Ok(Op::Eq),
+            "ne" => Ok(Op::Ne),
+            "where" => Ok(Op::Select),
+            "argmax" => Ok(Op::ArgMax),
+            "argmin" => Ok(Op::ArgMin),
+            "gather" => Ok(Op::Gather),
+            "scatter" => Ok(Op::Scatter),
+            "concat" => Ok(Op::Concat),
+            "zeros" => Ok(Op::Zeros),
+            "ones" => Ok(Op::Ones),
+            _ => Err(format!("Unknown op '{s}'")),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -78,3 +238,98 @@ pub enum Combinator {
 
 #[derive(Clone, Debug, Eq, Hash, PartialEq)]
 pub struct Symbol(pub String);
+
+/// Element type of an array
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum DType {
+    #[default]
+    F32,
+    F64,
+    F16,  // software emulated: stored as IEEE binary16 bits, computed in f32
+    BF16, // software emulated: stored as bfloat16 bits, computed in f32
+    I32,
+    Bool,
+}
+
+impl DType {
+    /// The element type of an op combining `self` and `other`
+    pub fn promote(self, other: DType) -> DType {
+        use DType::*;
+        match (self, other) {
+            (a, b) if a == b => a,
+            (F64, _) | (_, F64) => F64,
+            (F32, _) | (_, F32) | (F16, BF16) | (BF16, F16) => F32,
+            (F16, _) | (_, F16) => F16,
+            (BF16, _) | (_, BF16) => BF16,
+            (I32, _) | (_, I32) => I32,
+            (Bool, Bool) => Bool,
+        }
+    }
+
+    /// The type arithmetic on elements of `self` is carried out in. The emulated and `Bool` types
+    /// compute in f32; `I32` computes in f64, which represents every i32 exactly.
+    pub fn compute(self) -> DType {
+        match self {
+            DType::F64 | DType::I32 => DType::F64,
+            _ => DType::F32,
+        }
+    }
+
+    /// The type a reduction into `self` accumulates in when accumulating wide: one step wider
+    /// than the compute type for f32, the compute type otherwise
+    pub fn accumulator(self) -> DType {
+        match self {
+            DType::F32 => DType::F64,
+            _ => self.compute(),
+        }
+    }
+
+    /// The least and greatest elements of `self`
+    pub fn bounds(self) -> (f64, f64) {
+        match self {
+            DType::I32 => (i32::MIN as f64, i32::MAX as f64),
+            DType::Bool => (0., 1.),
+            _ => (f64::NEG_INFINITY, f64::INFINITY),
+        }
+    }
+
+    /// Size of one element in bytes
+    pub fn size(self) -> usize {
+        match self {
+            DType::F64 => 8,
+            DType::F32 | DType::I32 => 4,
+            DType::F16 | DType::BF16 => 2,
+            DType::Bool => 1,
+        }
+    }
+}
+
+impl fmt::Display for DType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            DType::F32 => "f32",
+            DType::F64 => "f64",
+            DType::F16 => "f16",
+            DType::BF16 => "bf16",
+            DType::I32 => "i32",
+            DType::Bool => "bool",
+        };
+        write!(f, "{s}")
+    }
+}
+
+impl FromStr for DType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "f32" => Ok(DType::F32),
+            "f64" => Ok(DType::F64),
+            "f16" => Ok(DType::F16),
+            "bf16" => Ok(DType::BF16),
+            "i32" => Ok(DType::I32),
+            "bool" => Ok(DType::Bool),
+            _ => Err(format!("Unsupported dtype '{s}'")),
+        }
+    }
+}
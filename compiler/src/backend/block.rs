@@ -4,12 +4,19 @@ use crate::block::{Arg, Block, Expr, Program, Statement, Type};
 pub struct BlockBackend;
 
 impl Render for BlockBackend {
+    /// Render the entry points and the library as one list, in the order `block::parser::parse`
+    /// reads them back
     fn render(program: &Program) -> String {
-        format!(
-            "{}\n{}",
-            Self::render_block(&program.library, 0),
-            Self::render_statement(&program.exec, 0)
-        )
+        let statements = program
+            .ranks
+            .iter()
+            .chain(program.shapes.iter())
+            .chain(program.library.statements.iter())
+            .chain([&program.exec])
+            .map(|s| Self::render_statement(s, 1))
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!("(\n{statements}\n)")
     }
 }
 
@@ -32,10 +39,12 @@ impl BlockBackend {
         match t {
             Type::Int(false) => "i".into(),
             Type::Int(true) => "i!".into(),
-            Type::Array(false) => "a".into(),
-            Type::Array(true) => "a!".into(),
-            Type::ArrayRef(false) => "ar".into(),
-            Type::ArrayRef(true) => "ar!".into(),
+            Type::Scalar(false, dtype) => format!("s:{dtype}"),
+            Type::Scalar(true, dtype) => format!("s!:{dtype}"),
+            Type::Array(false, dtype) => format!("a:{dtype}"),
+            Type::Array(true, dtype) => format!("a!:{dtype}"),
+            Type::ArrayRef(false, dtype) => format!("ar:{dtype}"),
+            Type::ArrayRef(true, dtype) => format!("ar!:{dtype}"),
         }
     }
 
@@ -43,9 +52,11 @@ impl BlockBackend {
         match expr {
             Expr::Alloc {
                 initial_value,
+                dtype,
                 shape,
-            } => format!("(alloc {:.1} {})", initial_value, shape.join(" ")),
+            } => format!("(alloc {:.1} {} {})", initial_value, dtype, shape.join(" ")),
             Expr::Int(x) => format!("(int {x})"),
+            Expr::Literal(value, dtype) => format!("(lit {:.1} {dtype})", value),
             Expr::Ident(s) => format!("(id {s})"),
             Expr::Ref(s, true) => format!("(ref! {s})"),
             Expr::Ref(s, false) => format!("(ref {s})"),
@@ -60,6 +71,10 @@ impl BlockBackend {
             Expr::Indexed { ident, index } => {
                 format!("(index {ident} {})", Self::render_expr(index))
             }
+            Expr::Cast { value, from, to } => {
+                format!("(cast {from} {to} {})", Self::render_expr(value))
+            }
+            Expr::AsIndex(value) => format!("(as-index {})", Self::render_expr(value)),
         }
     }
 
@@ -89,6 +104,23 @@ impl BlockBackend {
                     Self::render_expr(value)
                 )
             }
+            Statement::AtomicAdd { left, right, dtype } => {
+                format!(
+                    "{}(atomic-add {}\n{}{}\n{}{})",
+                    ind,
+                    dtype,
+                    Self::indent(level + 1),
+                    Self::render_expr(left),
+                    Self::indent(level + 1),
+                    Self::render_expr(right)
+                )
+            }
+            Statement::If { condition, body } => format!(
+                "{}(if {} {})",
+                ind,
+                Self::render_expr(condition),
+                Self::render_block(body, level + 1)
+            ),
             Statement::Skip { index, bound } => format!("{}(skip {} {})", ind, index, bound),
             Statement::Loop {
                 index,
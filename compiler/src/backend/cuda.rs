@@ -4,6 +4,7 @@ use std::{
 };
 
 use crate::{
+    ast::{DType, Op},
     backend::Render,
     block::{Arg, Block, Expr, Program, Statement, Type},
 };
@@ -194,7 +195,10 @@ impl Kernel {
 
 impl Render for CudaBackend {
     fn render(program: &Program) -> String {
-        let mut output = "#include <cuda.h>\n#include <stdio.h>\n\n".to_string();
+        let mut output = "#include <cuda.h>\n#include <cuda_bf16.h>\n#include <cuda_fp16.h>\n#include <math.h>\n#include <stdio.h>\n\n".to_string();
+
+        // fills allocations with their initial value
+        output += "template <typename T> __global__ void fill(T* a, size_t n, T value) {size_t i = (size_t)blockIdx.x * blockDim.x + threadIdx.x; if (i < n) {a[i] = value;}}";
 
         let mut kernels = Vec::new();
 
@@ -219,40 +223,202 @@ impl Render for CudaBackend {
             }
         }
 
-        let last_statement = &program.exec;
-        if let Statement::Function { ident, args, body } = last_statement {
-            let params = args
-                .iter()
-                .map(CudaBackend::render_param)
-                .collect::<Vec<String>>()
-                .join(",");
+        output += &Self::render_rank(&program.ranks);
+        output += &Self::render_shape(&program.shapes);
+        output += &Self::render_workspace_size(&program.exec);
+        output += &Self::render_exec(&program.exec, &kernels, false);
+        output += &Self::render_exec(&program.exec, &kernels, true);
+
+        output
+    }
+}
+
+impl CudaBackend {
+    /// Render the host executive function. With `workspace`, the function is named
+    /// `{ident}_workspace` and takes its intermediate arrays from a caller-owned device buffer of at
+    /// least `workspace_size` bytes instead of calling `cudaMalloc`.
+    fn render_exec(statement: &Statement, kernels: &[Kernel], workspace: bool) -> String {
+        let Statement::Function { ident, args, body } = statement else {
+            panic!(
+                "Found non-Function Statement in root block: {:?}",
+                statement
+            );
+        };
+        let mut output = String::new();
+        let params = args
+            .iter()
+            .map(CudaBackend::render_param)
+            .collect::<Vec<String>>()
+            .join(",");
+        if workspace {
+            output +=
+                &format!("int {ident}_workspace({params},char* workspace) {{cudaError_t err;");
+        } else {
             output += &format!("int {ident}({params}) {{cudaError_t err;");
-            for statement in body.statements.iter() {
-                match statement {
-                    Statement::Call { ident, .. } => {
-                        if let Some(kernel) = kernels.iter().find(|k| k.ident == *ident) {
-                            // TODO omit cudaDeviceSynchronize() for last kernel
-                            output += &kernel.render_call_site();
-                        } else {
-                            output += &CudaBackend::render_statement(statement);
-                        }
+        }
+        for statement in body.statements.iter() {
+            match statement {
+                Statement::Call { ident, .. } => {
+                    if let Some(kernel) = kernels.iter().find(|k| k.ident == *ident) {
+                        // TODO omit cudaDeviceSynchronize() for last kernel
+                        output += &kernel.render_call_site();
+                    } else {
+                        output += &CudaBackend::render_statement(statement);
                     }
-                    _ => output += &CudaBackend::render_statement(statement),
                 }
+                Statement::Declaration {
+                    ident,
+                    value:
+                        Expr::Alloc {
+                            initial_value,
+                            dtype,
+                            shape,
+                        },
+                    ..
+                } if workspace => {
+                    let ctype = Self::render_dtype(dtype);
+                    output += &format!("{ctype} *{ident} = ({ctype}*)workspace;");
+                    output += &format!(
+                        "workspace += {};",
+                        Self::render_workspace_bytes(shape, dtype)
+                    );
+                    output += &Self::render_fill(ident, shape, *initial_value, dtype);
+                }
+                _ => output += &CudaBackend::render_statement(statement),
             }
-            output += "return 0;}";
-        } else {
+        }
+        output += "return 0;}";
+        output
+    }
+    /// Render a `switch` on `output` running the body of the function for that output
+    fn render_output_switch(functions: &[Statement]) -> String {
+        let cases = functions
+            .iter()
+            .enumerate()
+            .map(|(ind, function)| {
+                let Statement::Function { body, .. } = function else {
+                    panic!(
+                        "Found non-Function Statement for output {ind}: {:?}",
+                        function
+                    );
+                };
+                let body = body
+                    .statements
+                    .iter()
+                    .map(Self::render_statement)
+                    .collect::<String>();
+                format!("case {ind}: {{{body}}} break;")
+            })
+            .collect::<String>();
+        format!("switch (output) {{{cases}}}")
+    }
+
+    /// Render `n_outputs`, and `rank`, the number of dimensions of an output
+    fn render_rank(ranks: &[Statement]) -> String {
+        format!(
+            "size_t n_outputs() {{return {};}}size_t rank(size_t output) {{{}return 0;}}",
+            ranks.len(),
+            Self::render_output_switch(ranks)
+        )
+    }
+
+    /// Render `shape`, filling `shape` with the extent of each dimension of an output from the
+    /// shape of each input in `input_shapes`
+    fn render_shape(shapes: &[Statement]) -> String {
+        let Some(Statement::Function { args, .. }) = shapes.first() else {
+            panic!("Found no Function Statement for shape.");
+        };
+        let n_inputs = args
+            .iter()
+            .filter(|arg| matches!(arg.type_, Type::ArrayRef(false, _)))
+            .count();
+        let input_shapes = (0..n_inputs)
+            .map(|ind| format!("const size_t* d{ind} = input_shapes[{ind}];"))
+            .collect::<String>();
+        format!(
+            "void shape(size_t output, const size_t* const* input_shapes, size_t* shape) {{{input_shapes}{}}}",
+            Self::render_output_switch(shapes)
+        )
+    }
+
+    /// Render `workspace_size`, the number of bytes of device memory `f_workspace` needs. Takes
+    /// the integer (dimension) parameters of `f`.
+    fn render_workspace_size(statement: &Statement) -> String {
+        let Statement::Function { args, body, .. } = statement else {
             panic!(
                 "Found non-Function Statement in root block: {:?}",
-                last_statement
+                statement
             );
+        };
+        let params = args
+            .iter()
+            .filter(|arg| matches!(arg.type_, Type::Int(_)))
+            .map(CudaBackend::render_param)
+            .collect::<Vec<String>>()
+            .join(",");
+        // the bounds of indices solved from affine dimensions size some allocs
+        let bounds = body
+            .statements
+            .iter()
+            .filter(|statement| {
+                matches!(
+                    statement,
+                    Statement::Declaration {
+                        type_: Type::Int(_),
+                        ..
+                    }
+                )
+            })
+            .map(Self::render_statement)
+            .collect::<String>();
+        let sizes = body
+            .statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::Declaration {
+                    value: Expr::Alloc { shape, dtype, .. },
+                    ..
+                } => Some(format!("+{}", Self::render_workspace_bytes(shape, dtype))),
+                _ => None,
+            })
+            .collect::<String>();
+        format!("size_t workspace_size({params}) {{{bounds}return 0{sizes};}}")
+    }
+    /// Bytes of workspace an allocation takes, rounded up to keep the next one 256-byte aligned
+    /// (matching `cudaMalloc`)
+    fn render_workspace_bytes(shape: &[String], dtype: &DType) -> String {
+        let len = match shape.is_empty() {
+            true => "1".to_string(),
+            false => shape.join("*"),
+        };
+        let ctype = Self::render_dtype(dtype);
+        format!("(({len})*sizeof({ctype})+255)/256*256")
+    }
+    fn render_fill(ident: &str, shape: &[String], value: f64, dtype: &DType) -> String {
+        let len = match shape.is_empty() {
+            true => "1".to_string(),
+            false => shape.join("*"),
+        };
+        format!(
+            "fill<<<({len}+255)/256, 256>>>({ident}, {len}, {});",
+            Self::render_literal(value, dtype)
+        )
+    }
+    fn render_literal(value: f64, dtype: &DType) -> String {
+        let float = |value: f64| match value {
+            v if v == f64::INFINITY => "INFINITY".to_string(),
+            v if v == f64::NEG_INFINITY => "-INFINITY".to_string(),
+            v => format!("{v:?}"),
+        };
+        match dtype {
+            DType::F32 => format!("(float){}", float(value)),
+            DType::F64 => float(value),
+            DType::F16 => format!("__float2half({})", float(value)),
+            DType::BF16 => format!("__float2bfloat16({})", float(value)),
+            DType::I32 => format!("{}", value as i32), // saturates infinities to INT_MIN/INT_MAX
+            DType::Bool => format!("{}", value > 0.),  // so that -inf is false
         }
-
-        output
     }
-}
-
-impl CudaBackend {
     fn render_arg(arg: &Arg) -> String {
         let Arg { ident, .. } = arg;
         format!("{}", CudaBackend::render_expr(ident))
@@ -268,7 +434,35 @@ impl CudaBackend {
     fn render_type(type_: &Type) -> String {
         match type_ {
             Type::Int(_) => "int".to_string(),
-            Type::Array(_) | Type::ArrayRef(_) => "float*".to_string(),
+            Type::Scalar(_, dtype) => Self::render_dtype(dtype).to_string(),
+            Type::Array(_, dtype) | Type::ArrayRef(_, dtype) => {
+                format!("{}*", Self::render_dtype(dtype))
+            }
+        }
+    }
+    fn render_dtype(dtype: &DType) -> &'static str {
+        match dtype {
+            DType::F32 => "float",
+            DType::F64 => "double",
+            DType::F16 => "__half",
+            DType::BF16 => "__nv_bfloat16",
+            DType::I32 => "int",
+            DType::Bool => "bool",
+        }
+    }
+    fn render_cast(value: &Expr, from: &DType, to: &DType) -> String {
+        let value = Self::render_expr(value);
+        // the cuda_fp16/cuda_bf16 conversions go through float
+        let (value, from) = match from {
+            DType::F16 => (format!("__half2float({value})"), DType::F32),
+            DType::BF16 => (format!("__bfloat162float({value})"), DType::F32),
+            _ => (value, *from),
+        };
+        match (from, to) {
+            (from, to) if from == *to => value,
+            (_, DType::F16) => format!("__float2half((float)({value}))"),
+            (_, DType::BF16) => format!("__float2bfloat16((float)({value}))"),
+            (_, to) => format!("(({})({value}))", Self::render_dtype(to)),
         }
     }
     fn render_statement(statement: &Statement) -> String {
@@ -278,6 +472,11 @@ impl CudaBackend {
                 Self::render_expr(left),
                 Self::render_expr(right)
             ),
+            Statement::AtomicAdd { left, right, .. } => format!(
+                "atomicAdd(&{}, {});",
+                Self::render_expr(left),
+                Self::render_expr(right)
+            ),
             Statement::Declaration {
                 ident,
                 value,
@@ -285,15 +484,20 @@ impl CudaBackend {
                 ..
             } => {
                 if let Expr::Alloc {
-                    initial_value: _, // TODO handle initial values?
+                    initial_value,
+                    dtype,
                     shape,
                 } = value
                 {
                     // TODO maybe declaration and allocation should be separate
                     let shape_str = shape.join("*");
-                    let mut output = format!("float *{ident};cudaMalloc(&{ident},{shape_str}*4);");
+                    let ctype = Self::render_dtype(dtype);
+                    let mut output = format!(
+                        "{ctype} *{ident};cudaMalloc(&{ident},{shape_str}*sizeof({ctype}));"
+                    );
                     output += "err = cudaGetLastError();";
                     output += &format!("if (err != cudaSuccess) {{fprintf(stderr, \"cudaMalloc for {ident} failed: %s\\n\", cudaGetErrorString(err));}}");
+                    output += &Self::render_fill(ident, shape, *initial_value, dtype);
                     output
                 } else {
                     let rendered_type = CudaBackend::render_type(type_);
@@ -301,6 +505,15 @@ impl CudaBackend {
                     format!("{rendered_type} {ident} = {rendered_value};")
                 }
             }
+            Statement::If { condition, body } => format!(
+                "if ({}) {{{}}}",
+                Self::render_expr(condition),
+                body.statements
+                    .iter()
+                    .map(CudaBackend::render_statement)
+                    .collect::<Vec<String>>()
+                    .join("")
+            ),
             Statement::Skip { index, bound } => format!("if ({index} >= {bound}) {{ continue; }}"),
             Statement::Loop {
                 index, bound, body, ..
@@ -313,7 +526,7 @@ impl CudaBackend {
                     .collect::<Vec<String>>()
                     .join("")
             ),
-            Statement::Return { .. } => todo!(),
+            Statement::Return { value } => format!("return {};", Self::render_expr(value)),
             Statement::Function { .. } => unreachable!("Reached a Function inside a Function"),
             Statement::Call { ident, args } => {
                 let args = args
@@ -329,42 +542,57 @@ impl CudaBackend {
         let Expr::Op { op, inputs } = expr else {
             panic!("Expected `Op` variant of `Expr`")
         };
-        match op {
-            '>' => match inputs.len() {
-                1 => format!(
-                    "{} > 0. ? {} : 0.",
-                    Self::render_expr(&inputs[0]),
-                    Self::render_expr(&inputs[0]),
-                ),
-                2 => format!(
-                    "{} > {} ? {} : {}",
-                    Self::render_expr(&inputs[0]),
-                    Self::render_expr(&inputs[1]),
-                    Self::render_expr(&inputs[0]),
-                    Self::render_expr(&inputs[1]),
-                ),
-                _ => panic!("Expected 1 or 2 inputs to op `>`."),
-            },
-            _ => format!(
+        let input = |i: usize| Self::render_expr(&inputs[i]);
+        match (op, inputs.len()) {
+            (Op::Max, 1) => format!("({x} > 0. ? {x} : 0.)", x = input(0)),
+            (Op::Max, 2) => format!("({a} > {b} ? {a} : {b})", a = input(0), b = input(1)),
+            (Op::Min, 1) => format!("({x} < 0. ? {x} : 0.)", x = input(0)),
+            (Op::Min, 2) => format!("({a} < {b} ? {a} : {b})", a = input(0), b = input(1)),
+            (Op::Sub, 1) => format!("-({})", input(0)),
+            (Op::Div, 1) => format!("1. / {}", input(0)),
+            (Op::Pow, 2) => format!("pow({}, {})", input(0), input(1)),
+            // the math functions are overloaded, so `double` kernels stay in double precision
+            (Op::Exp, 1) => format!("exp({})", input(0)),
+            (Op::Log, 1) => format!("log({})", input(0)),
+            (Op::Abs, 1) => format!("fabs({})", input(0)),
+            (Op::Sqrt, 1) => format!("sqrt({})", input(0)),
+            (Op::Rsqrt, 1) => format!("rsqrt({})", input(0)),
+            (Op::Tanh, 1) => format!("tanh({})", input(0)),
+            (Op::Sigmoid, 1) => format!("(1. / (1. + exp(-({}))))", input(0)),
+            (Op::Sin, 1) => format!("sin({})", input(0)),
+            (Op::Cos, 1) => format!("cos({})", input(0)),
+            (Op::Floor, 1) => format!("floor({})", input(0)),
+            (Op::Lt, 2) => format!("({} < {})", input(0), input(1)),
+            (Op::Le, 2) => format!("({} <= {})", input(0), input(1)),
+            (Op::Gt, 2) => format!("({} > {})", input(0), input(1)),
+            (Op::Ge, 2) => format!("({} >= {})", input(0), input(1)),
+            (Op::Eq, 2) => format!("({} == {})", input(0), input(1)),
+            (Op::Ne, 2) => format!("({} != {})", input(0), input(1)),
+            (Op::Select, 3) => format!("({} ? {} : {})", input(0), input(1), input(2)),
+            (Op::Id | Op::Add | Op::Sub | Op::Mul | Op::Div, _) => format!(
                 "({})",
                 inputs
                     .iter()
-                    .map(|input| Self::render_expr(&input))
+                    .map(Self::render_expr)
                     .collect::<Vec<_>>()
                     .join(&format!(" {op} "))
             ),
+            (op, n) => panic!("Unexpected {n} inputs to op [{op}]."),
         }
     }
     fn render_expr(expr: &Expr) -> String {
         match expr {
             Expr::Ident(s) => s.to_string(),
             Expr::Int(x) => format!("{x}"),
+            Expr::Literal(value, dtype) => Self::render_literal(*value, dtype),
             Expr::Op { .. } => Self::render_op(&expr),
             Expr::Indexed { ident, index } => format!("{ident}[{}]", Self::render_expr(&index)),
             Expr::Alloc { .. } => {
                 unreachable!("Expr::Alloc should be handled in Statement::Declaration")
             }
             Expr::Ref(ident, _) => ident.to_string(),
+            Expr::Cast { value, from, to } => Self::render_cast(value, from, to),
+            Expr::AsIndex(value) => Self::render_expr(value),
         }
     }
 }
@@ -1,4 +1,4 @@
-use std::{io::Error, path::PathBuf};
+use std::{fmt, io, path::Path, process::ExitStatus};
 
 use crate::block::Program;
 
@@ -13,7 +13,51 @@ pub trait Render {
 #[allow(dead_code)]
 // Not dead code, but the compiler thinks so...?
 pub trait Build {
-    fn build(source: &str) -> Result<PathBuf, Error>;
+    /// Build `source` into the library at `dylib_path`
+    fn build(source: &str, dylib_path: &Path) -> Result<(), BuildError>;
+
+    /// What a build depends on besides its source, e.g. the compiler's version and flags
+    fn fingerprint() -> String;
+}
+
+/// Why a build failed
+#[derive(Debug)]
+pub enum BuildError {
+    /// Its files couldn't be written, or the compiler couldn't be run
+    Io(io::Error),
+    /// The compiler ran on the rendered `source` with `command` and failed
+    Compiler {
+        source: String,
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::Io(e) => write!(f, "Build failed: {e}"),
+            BuildError::Compiler {
+                command,
+                status,
+                stderr,
+                ..
+            } => write!(
+                f,
+                "Build failed with {status}: {command}\n{}",
+                stderr.trim_end()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BuildError {}
+
+impl From<io::Error> for BuildError {
+    fn from(e: io::Error) -> Self {
+        BuildError::Io(e)
+    }
 }
 
 #[allow(dead_code)]
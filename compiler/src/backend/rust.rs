@@ -1,54 +1,63 @@
-use std::collections::HashMap;
 use std::fs;
-use std::io::Error;
-use std::path::PathBuf;
+use std::path::Path;
 use std::process::Command;
 
-use crate::backend::{Backend, Build, Render};
+use crate::ast::{DType, Op};
+use crate::backend::{Backend, Build, BuildError, Render};
 use crate::block::{Arg, Block, Expr, Program, Statement, Type};
 
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::tempdir::TempDir;
 
-fn unique_string() -> String {
-    format!(
-        "{}",
-        SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos()
-    )
-}
+use std::sync::OnceLock;
+
+const RUSTC_FLAGS: [&str; 5] = ["--crate-type=dylib", "-C", "opt-level=3", "-A", "warnings"];
 
 pub struct RustBackend;
 
 impl Backend for RustBackend {}
 
 impl Build for RustBackend {
-    fn build(source: &str) -> Result<PathBuf, Error> {
-        let path_base = "/tmp/ilang";
-        let source_path = format!("{path_base}.rs");
-        let dylib_path = format!("{path_base}_{}.so", unique_string());
+    fn build(source: &str, dylib_path: &Path) -> Result<(), BuildError> {
+        // rustc writes intermediate files beside its output, so each build gets a directory
+        let dir = TempDir::new()?;
+        let source_path = dir.path().join("ilang.rs");
+        let built_path = dir.path().join("ilang.so");
         fs::write(&source_path, source)?;
-        let build = Command::new("rustc")
-            .args([
-                "--crate-type=dylib",
-                "-C",
-                "opt-level=3",
-                &source_path,
-                "-o",
-                &dylib_path,
-                "-A",
-                "warnings",
-            ])
-            .status();
-        if let Err(e) = build {
-            return Err(e);
-        }
-        let exit = build.unwrap();
-        if !exit.success() {
-            return Err(Error::last_os_error());
+        let mut command = Command::new("rustc");
+        command
+            .args(RUSTC_FLAGS)
+            .arg(&source_path)
+            .arg("-o")
+            .arg(&built_path);
+        let output = command.output()?;
+        if !output.status.success() {
+            let args = command.get_args().map(|arg| arg.to_string_lossy());
+            return Err(BuildError::Compiler {
+                source: source.to_string(),
+                command: ["rustc".into()]
+                    .into_iter()
+                    .chain(args)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
         }
-        Ok(PathBuf::from(dylib_path))
+        // copied rather than renamed, as the temporary directory may be another filesystem
+        fs::copy(built_path, dylib_path)?;
+        Ok(())
+    }
+
+    fn fingerprint() -> String {
+        static VERSION: OnceLock<String> = OnceLock::new();
+        let version = VERSION.get_or_init(|| {
+            Command::new("rustc")
+                .arg("--version")
+                .output()
+                .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+                .unwrap_or_default()
+        });
+        format!("{version} {}", RUSTC_FLAGS.join(" "))
     }
 }
 
@@ -58,20 +67,41 @@ impl Render for RustBackend {
             r#"
 #[repr(C)]
 struct Tensor<'a> {{
-    data: *const f32,
+    data: *const std::ffi::c_void,
     shape: *const usize,
     ndim: usize,
-    _marker: std::marker::PhantomData<&'a [f32]>,
+    _marker: std::marker::PhantomData<&'a [u8]>,
 }}
 
 #[repr(C)]
 struct TensorMut<'a> {{
-    data: *mut f32,
+    data: *mut std::ffi::c_void,
     shape: *const usize,
     ndim: usize,
-    _marker: std::marker::PhantomData<&'a mut [f32]>,
+    _marker: std::marker::PhantomData<&'a mut [u8]>,
 }}
 
+// f16 and bf16 elements are stored as their bit patterns and computed in f32
+{}
+
+// Carve a `len`-element slice out of the caller-owned workspace, filled with `value`
+unsafe fn workspace_slice<'a, T: Copy>(workspace: &mut *mut u8, len: usize, value: T) -> &'a mut [T] {{
+    let ptr = workspace.add(workspace.align_offset(std::mem::align_of::<T>())) as *mut T;
+    *workspace = ptr.add(len) as *mut u8;
+    let slice = std::slice::from_raw_parts_mut(ptr, len);
+    slice.fill(value);
+    slice
+}}
+
+// Worst-case bytes `workspace_slice` consumes for `len` elements, including alignment padding
+fn workspace_bytes<T>(len: usize) -> usize {{
+    len * std::mem::size_of::<T>() + std::mem::align_of::<T>() - 1
+}}
+
+{}
+
+{}
+
 {}
 
 {}
@@ -80,10 +110,13 @@ struct TensorMut<'a> {{
 
 {}
 "#,
-            Self::render_rank(&program.rank),
-            Self::render_shape(&program.shape),
+            include_str!("../half.rs"),
+            Self::render_rank(&program.ranks),
+            Self::render_shape(&program.shapes),
+            Self::render_workspace_size(&program.exec),
             Self::render_block(&program.library),
-            Self::render_exec(&program.exec)
+            Self::render_exec(&program.exec),
+            Self::render_exec_workspace(&program.exec)
         )
     }
 }
@@ -100,134 +133,344 @@ impl RustBackend {
     fn render_type(type_: &Type) -> String {
         match type_ {
             Type::Int(_) => "usize".to_string(),
-            Type::Array(mutable) | Type::ArrayRef(mutable) => {
-                format!("&{}[f32]", if *mutable { "mut " } else { "" })
+            Type::Scalar(_, dtype) => Self::render_dtype(dtype).to_string(),
+            Type::Array(mutable, dtype) | Type::ArrayRef(mutable, dtype) => {
+                format!(
+                    "&{}[{}]",
+                    if *mutable { "mut " } else { "" },
+                    Self::render_dtype(dtype)
+                )
             }
         }
     }
+    /// The Rust type elements of `dtype` are stored as
+    fn render_dtype(dtype: &DType) -> &'static str {
+        match dtype {
+            DType::F32 => "f32",
+            DType::F64 => "f64",
+            DType::F16 | DType::BF16 => "u16",
+            DType::I32 => "i32",
+            DType::Bool => "bool",
+        }
+    }
+    /// Render `value` as a stored element of `dtype`
+    fn render_literal(value: f64, dtype: &DType) -> String {
+        let float = |value: f64, ty: &str| match value {
+            v if v == f64::INFINITY => format!("{ty}::INFINITY"),
+            v if v == f64::NEG_INFINITY => format!("{ty}::NEG_INFINITY"),
+            v => format!("{v:?}"), // using `.to_string()` won't produce decimal
+        };
+        match dtype {
+            DType::F32 => float(value, "f32"),
+            DType::F64 => float(value, "f64"),
+            DType::F16 => format!("f32_to_f16({})", float(value, "f32")),
+            DType::BF16 => format!("f32_to_bf16({})", float(value, "f32")),
+            DType::I32 => format!("{}", value as i32), // saturates infinities to i32::MIN/MAX
+            DType::Bool => format!("{}", value > 0.),  // so that -inf is false
+        }
+    }
+    fn render_cast(value: &Expr, from: &DType, to: &DType) -> String {
+        let value = Self::render_expr(value);
+        // widen the emulated and boolean types to a native numeric type first
+        let (value, from) = match from {
+            DType::F16 => (format!("f16_to_f32({value})"), DType::F32),
+            DType::BF16 => (format!("bf16_to_f32({value})"), DType::F32),
+            DType::Bool if *to != DType::Bool => (format!("({value} as u8)"), DType::I32),
+            _ => (value, *from),
+        };
+        match (from, to) {
+            (DType::I32, DType::I32) => format!("({value} as i32)"), // from index arithmetic
+            (from, to) if from == *to => value,
+            (DType::Bool, _) => unreachable!(),
+            (DType::I32, DType::Bool) => format!("({value} != 0)"),
+            (_, DType::Bool) => format!("({value} != 0.)"),
+            (_, DType::F16) => format!("f32_to_f16({value} as f32)"),
+            (_, DType::BF16) => format!("f32_to_bf16({value} as f32)"),
+            (_, to) => format!("({value} as {})", Self::render_dtype(to)),
+        }
+    }
     fn render_op(expr: &Expr) -> String {
         let Expr::Op { op, inputs } = expr else {
             panic!("Expected `Op` variant of `Expr`")
         };
-        match op {
-            '>' => match inputs.len() {
-                1 => format!(
-                    "if {} > 0. {{ {} }} else {{ 0. }}",
-                    Self::render_expr(&inputs[0]),
-                    Self::render_expr(&inputs[0]),
-                ),
-                2 => format!(
-                    "if {} > {} {{ {} }} else {{ {} }}",
-                    Self::render_expr(&inputs[0]),
-                    Self::render_expr(&inputs[1]),
-                    Self::render_expr(&inputs[0]),
-                    Self::render_expr(&inputs[1]),
-                ),
-                _ => panic!("Expected 1 or 2 inputs to op [>]."),
-            },
-            '^' => {
-                assert!(inputs.len() == 1, "Expected 1 input to op [^].");
-                format!("{}.exp()", Self::render_expr(&inputs[0]))
-            }
-            '$' => {
-                assert!(inputs.len() == 1, "Expected 1 input to op [$].");
-                format!("({} as f64).ln() as f32", Self::render_expr(&inputs[0]))
-            }
-            c => {
-                if inputs.len() == 1 {
-                    if *c == '-' {
-                        return format!("-({})", Self::render_expr(&inputs[0]));
-                    }
-                    if *c == '/' {
-                        return format!("1. / {}", Self::render_expr(&inputs[0]));
-                    }
-                }
-                format!(
-                    "({})",
-                    inputs
-                        .iter()
-                        .map(|input| Self::render_expr(&input))
-                        .collect::<Vec<_>>()
-                        .join(&format!(" {op} "))
-                )
-            }
+        let input = |i: usize| Self::render_expr(&inputs[i]);
+        match (op, inputs.len()) {
+            (Op::Max, 1) => format!("if {x} > 0. {{ {x} }} else {{ 0. }}", x = input(0)),
+            (Op::Max, 2) => format!(
+                "if {a} > {b} {{ {a} }} else {{ {b} }}",
+                a = input(0),
+                b = input(1)
+            ),
+            (Op::Min, 1) => format!("if {x} < 0. {{ {x} }} else {{ 0. }}", x = input(0)),
+            (Op::Min, 2) => format!(
+                "if {a} < {b} {{ {a} }} else {{ {b} }}",
+                a = input(0),
+                b = input(1)
+            ),
+            (Op::Sub, 1) => format!("-({})", input(0)),
+            (Op::Div, 1) => format!("1. / {}", input(0)),
+            (Op::Pow, 2) => format!("{}.powf({})", input(0), input(1)),
+            (Op::Exp, 1) => format!("{}.exp()", input(0)),
+            (Op::Log, 1) => format!("{}.ln()", input(0)),
+            (Op::Abs, 1) => format!("{}.abs()", input(0)),
+            (Op::Sqrt, 1) => format!("{}.sqrt()", input(0)),
+            (Op::Rsqrt, 1) => format!("(1. / {}.sqrt())", input(0)),
+            (Op::Tanh, 1) => format!("{}.tanh()", input(0)),
+            (Op::Sigmoid, 1) => format!("(1. / (1. + (-{}).exp()))", input(0)),
+            (Op::Sin, 1) => format!("{}.sin()", input(0)),
+            (Op::Cos, 1) => format!("{}.cos()", input(0)),
+            (Op::Floor, 1) => format!("{}.floor()", input(0)),
+            (Op::Lt, 2) => format!("({} < {})", input(0), input(1)),
+            (Op::Le, 2) => format!("({} <= {})", input(0), input(1)),
+            (Op::Gt, 2) => format!("({} > {})", input(0), input(1)),
+            (Op::Ge, 2) => format!("({} >= {})", input(0), input(1)),
+            (Op::Eq, 2) => format!("({} == {})", input(0), input(1)),
+            (Op::Ne, 2) => format!("({} != {})", input(0), input(1)),
+            (Op::Select, 3) => format!(
+                "(if {} {{ {} }} else {{ {} }})",
+                input(0),
+                input(1),
+                input(2)
+            ),
+            (Op::Id | Op::Add | Op::Sub | Op::Mul | Op::Div, _) => format!(
+                "({})",
+                inputs
+                    .iter()
+                    .map(Self::render_expr)
+                    .collect::<Vec<_>>()
+                    .join(&format!(" {op} "))
+            ),
+            (op, n) => panic!("Unexpected {n} inputs to op [{op}]."),
         }
     }
     fn render_expr(expr: &Expr) -> String {
         match expr {
             Expr::Alloc {
                 initial_value,
+                dtype,
                 shape,
             } => {
                 format!(
                     "&mut vec![{}; {}][..]",
-                    format!("{:.1}", initial_value), // using `.to_string()` won't produce decimal
-                    format!("{}", shape.join(" * ")),
+                    Self::render_literal(*initial_value, dtype),
+                    Self::render_len(shape),
                 )
             }
             Expr::Ident(s) => s.to_string(),
             Expr::Ref(s, _mutable) => format!("{s}"),
             Expr::Int(x) => format!("{x}"),
+            Expr::Literal(value, dtype) => Self::render_literal(*value, dtype),
             Expr::Op { .. } => Self::render_op(&expr),
             Expr::Indexed { ident, index } => format!("{ident}[{}]", Self::render_expr(&index),),
+            Expr::Cast { value, from, to } => Self::render_cast(value, from, to),
+            Expr::AsIndex(value) => format!("({} as usize)", Self::render_expr(value)),
         }
     }
 
-    fn render_rank(statement: &Statement) -> String {
-        if let Statement::Function { body, .. } = &statement {
-            format!(
-                r#"
+    /// Render a `match` on `output` running the body of the function for that output
+    fn render_output_match(functions: &[Statement]) -> String {
+        let arms = functions
+            .iter()
+            .enumerate()
+            .map(|(ind, function)| {
+                let Statement::Function { body, .. } = function else {
+                    panic!("Found non-`Function` `Statement` for output {ind}.")
+                };
+                format!("{ind} => {{\n{}\n}}", Self::render_block(body))
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        format!(
+            "match output {{\n{arms}\n_ => panic!(\"Expected an output below {}.\"),\n}}",
+            functions.len()
+        )
+    }
+
+    fn render_rank(ranks: &[Statement]) -> String {
+        format!(
+            r#"
 #[no_mangle]
 extern "C"
-fn rank() -> usize {{
+fn n_outputs() -> usize {{
+    {n_outputs}
+}}
+
+#[no_mangle]
+extern "C"
+fn rank(output: usize) -> usize {{
     {function_body}
 }}
 "#,
-                function_body = Self::render_block(&body),
+            n_outputs = ranks.len(),
+            function_body = Self::render_output_match(ranks),
+        )
+    }
+
+    fn render_shape(shapes: &[Statement]) -> String {
+        let Some(Statement::Function { args, .. }) = shapes.first() else {
+            panic!("Found no `Function` `Statement` for shape function.")
+        };
+        let n_input_arrays = args
+            .iter()
+            .filter(|arg| matches!(arg.type_, Type::ArrayRef(false, _)))
+            .count();
+        let input_shape_vecs_string = (0..n_input_arrays)
+            .map(|ind| {
+                format!(
+                "let d{ind} = std::slice::from_raw_parts(inputs[{ind}].shape, inputs[{ind}].ndim);"
             )
-        } else {
-            panic!("Found non-`Function` `Statement` for executive function.")
-        }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let output_shape_vec_string = "let shape = std::slice::from_raw_parts_mut(shape, rank);";
+
+        format!(
+            r#"
+#[no_mangle]
+unsafe extern "C"
+fn shape(output: usize, inputs: *const Tensor, n_inputs: usize, rank: usize, shape: *mut usize) {{
+    let inputs = std::slice::from_raw_parts(inputs, n_inputs);
+
+    {input_shape_vecs_string}
+    {output_shape_vec_string}
+
+    {function_body}
+}}
+"#,
+            function_body = Self::render_output_match(shapes),
+        )
     }
 
-    fn render_shape(statement: &Statement) -> String {
-        if let Statement::Function { ident, args, body } = &statement {
-            let mut inputs_arrays = args
-                .iter()
-                .filter(|arg| matches!(arg.type_, Type::ArrayRef(_)))
-                .collect::<Vec<_>>();
+    /// Render the `let` bindings shared by the entry points that read `inputs`: the input
+    /// shapes and the loop bounds taken from them. With `output`, also bind the output shapes, the
+    /// input and output arrays, and the output bounds.
+    fn render_exec_preamble(args: &[Arg], output: bool) -> String {
+        let input_dtypes = args
+            .iter()
+            .filter_map(|arg| match arg.type_ {
+                Type::ArrayRef(false, dtype) => Some(Self::render_dtype(&dtype)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let outputs = args
+            .iter()
+            .filter_map(|arg| match (&arg.type_, &arg.ident) {
+                (Type::ArrayRef(true, dtype), Expr::Ident(ident)) => {
+                    Some((ident, Self::render_dtype(dtype)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
 
-            let inputs_dims = args
-                .iter()
-                .filter(|arg| matches!(arg.type_, Type::Int(_)))
-                .collect::<Vec<_>>();
+        let input_shape_vecs_string = (0..input_dtypes.len())
+            .map(|ind| {
+                format!(
+                "let d{ind} = std::slice::from_raw_parts(inputs[{ind}].shape, inputs[{ind}].ndim);"
+            )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
 
-            let _ = inputs_arrays.pop(); // TODO: What if there are no inputs?
+        let input_arrays_string = input_dtypes
+            .iter()
+            .enumerate()
+            .map(|(ind, dtype)| format!(
+                "let in{ind} = std::slice::from_raw_parts(inputs[{ind}].data as *const {dtype}, d{ind}.iter().product());"
+            ))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        // map bound idents (i.e., `b0`, `b1`, ...) to `d0[0]`, `d1[0]`, etc.
+        // that is: `d{array_ind}[{bound_ind}]`, or `d{output_ident}[{bound_ind}]` for outputs
+        let mut bound_variable_string = String::new();
+        let mut input_ind = 0;
+        let mut shape_vec_string = None;
+        let mut array_dim_ind = 0;
+        for arg in args {
+            match (&arg.type_, &arg.ident) {
+                (Type::ArrayRef(false, _), _) => {
+                    shape_vec_string = Some(format!("d{input_ind}"));
+                    input_ind += 1;
+                    array_dim_ind = 0;
+                }
+                (Type::ArrayRef(true, _), Expr::Ident(ident)) => {
+                    shape_vec_string = output.then(|| format!("d{ident}"));
+                    array_dim_ind = 0;
+                }
+                (Type::Int(_), Expr::Ident(bound_ident)) => {
+                    array_dim_ind += 1;
+                    let Some(shape_vec_string) = &shape_vec_string else {
+                        continue;
+                    };
+                    bound_variable_string.push_str(&format!(
+                        "let {bound_ident} = {shape_vec_string}[{}];",
+                        array_dim_ind - 1
+                    ))
+                }
+                _ => panic!("Unexpected arg type in exec function."),
+            }
+        }
 
-            let n_input_arrays = inputs_arrays.len();
-            let input_shape_vecs_string = (0..n_input_arrays)
-                .map(|ind| format!(
-                    "let d{ind} = std::slice::from_raw_parts(inputs[{ind}].shape, inputs[{ind}].ndim);"
-                ))
-                .collect::<Vec<_>>()
-                .join("\n");
+        if !output {
+            return format!(
+                r#"
+    let inputs = std::slice::from_raw_parts(inputs, n_inputs);
 
-            let output_shape_vec_string =
-                "let shape = std::slice::from_raw_parts_mut(shape, rank);";
+    {input_shape_vecs_string}
 
+    {bound_variable_string}
+"#
+            );
+        }
+
+        let output_shape_vecs_string = outputs
+            .iter()
+            .enumerate()
+            .map(|(ind, (ident, _))| format!(
+                "let d{ident} = std::slice::from_raw_parts(outputs[{ind}].shape, outputs[{ind}].ndim);"
+            ))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let output_arrays_string = outputs
+            .iter()
+            .enumerate()
+            .map(|(ind, (ident, dtype))| format!(
+                "let {ident} = std::slice::from_raw_parts_mut(outputs[{ind}].data as *mut {dtype}, d{ident}.iter().product());"
+            ))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        format!(
+            r#"
+    let inputs = std::slice::from_raw_parts(inputs, n_inputs);
+    let outputs = std::slice::from_raw_parts_mut(outputs, n_outputs);
+
+    {input_shape_vecs_string}
+    {output_shape_vecs_string}
+
+    {input_arrays_string}
+    {output_arrays_string}
+
+    {bound_variable_string}
+"#
+        )
+    }
+
+    fn render_exec(statement: &Statement) -> String {
+        if let Statement::Function { args, body, .. } = &statement {
             format!(
                 r#"
 #[no_mangle]
 unsafe extern "C"
-fn shape(inputs: *const Tensor, n_inputs: usize, rank: usize, shape: *mut usize) {{
-    let inputs = std::slice::from_raw_parts(inputs, n_inputs);
-
-    {input_shape_vecs_string}
-    {output_shape_vec_string}
+fn f(inputs: *const Tensor, n_inputs: usize, outputs: *mut TensorMut, n_outputs: usize) {{
+    {preamble}
 
     {function_body}
 }}
 "#,
+                preamble = Self::render_exec_preamble(args, true),
                 function_body = Self::render_block(&body),
             )
         } else {
@@ -235,98 +478,119 @@ fn shape(inputs: *const Tensor, n_inputs: usize, rank: usize, shape: *mut usize)
         }
     }
 
-    fn render_exec(statement: &Statement) -> String {
-        if let Statement::Function { ident, args, body } = &statement {
-            let mut inputs_arrays = args
+    /// Render `workspace_size`, the number of bytes of scratch memory `f_workspace` needs for the
+    /// given inputs
+    fn render_workspace_size(statement: &Statement) -> String {
+        if let Statement::Function { args, body, .. } = &statement {
+            // the bounds of indices solved from affine dimensions size some allocs
+            let bounds = body
+                .statements
                 .iter()
-                .filter(|arg| matches!(arg.type_, Type::ArrayRef(_)))
-                .collect::<Vec<_>>();
-
-            let inputs_dims = args
+                .filter(|statement| {
+                    matches!(
+                        statement,
+                        Statement::Declaration {
+                            type_: Type::Int(_),
+                            ..
+                        }
+                    )
+                })
+                .map(Self::render_statement)
+                .collect::<Vec<_>>()
+                .join("\n");
+            let allocs = body
+                .statements
                 .iter()
-                .filter(|arg| matches!(arg.type_, Type::Int(_)))
+                .filter_map(|statement| match statement {
+                    Statement::Declaration {
+                        value: Expr::Alloc { dtype, shape, .. },
+                        ..
+                    } => Some(format!(
+                        "workspace_bytes::<{}>({})",
+                        Self::render_dtype(dtype),
+                        Self::render_len(shape)
+                    )),
+                    _ => None,
+                })
                 .collect::<Vec<_>>();
 
-            let _ = inputs_arrays.pop(); // TODO: What if there are no inputs?
+            format!(
+                r#"
+#[no_mangle]
+unsafe extern "C"
+fn workspace_size(inputs: *const Tensor, n_inputs: usize) -> usize {{
+    {preamble}
+    {bounds}
 
-            let n_input_arrays = inputs_arrays.len();
-            let input_shape_vecs_string = (0..n_input_arrays)
-                .map(|ind| format!(
-                    "let d{ind} = std::slice::from_raw_parts(inputs[{ind}].shape, inputs[{ind}].ndim);"
-                ))
-                .collect::<Vec<_>>()
-                .join("\n");
+    0 {sizes}
+}}
+"#,
+                preamble = Self::render_exec_preamble(args, false),
+                sizes = allocs
+                    .iter()
+                    .map(|size| format!("+ {size}"))
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+        } else {
+            panic!("Found non-`Function` `Statement` for executive function.")
+        }
+    }
 
-            let input_arrays_string = (0..inputs_arrays.len())
-                .map(|ind| format!(
-                    "let in{ind} = std::slice::from_raw_parts(inputs[{ind}].data, d{ind}.iter().product());"
-                ))
+    /// Render `f_workspace`, which behaves like `f` but takes its intermediate arrays from a
+    /// caller-owned buffer of at least `workspace_size` bytes instead of allocating them
+    fn render_exec_workspace(statement: &Statement) -> String {
+        if let Statement::Function { args, body, .. } = &statement {
+            let function_body = body
+                .statements
+                .iter()
+                .map(|statement| match statement {
+                    Statement::Declaration {
+                        ident,
+                        value:
+                            Expr::Alloc {
+                                initial_value,
+                                dtype,
+                                shape,
+                            },
+                        type_,
+                    } => format!(
+                        "let {ident}: {} = workspace_slice::<{}>(&mut workspace, {}, {});",
+                        Self::render_type(type_),
+                        Self::render_dtype(dtype),
+                        Self::render_len(shape),
+                        Self::render_literal(*initial_value, dtype),
+                    ),
+                    statement => Self::render_statement(statement),
+                })
                 .collect::<Vec<_>>()
                 .join("\n");
 
-            // map bound idents (i.e., `b0`, `b1`, ...) to `d0[0]`, `d1[0]`, etc.
-            // that is: `d{array_ind}[{bound_ind}]`
-            let mut bound_variable_string = String::new();
-            let mut array_arg_ind = 0;
-            let mut array_dim_ind = 0;
-            let mut dim_map: HashMap<String, String> = HashMap::new();
-            for arg in args {
-                match arg.type_ {
-                    Type::ArrayRef(_) => {
-                        array_arg_ind += 1;
-                        array_dim_ind = 0;
-                    }
-                    Type::Int(_) => {
-                        array_dim_ind += 1;
-                        let shape_vec_string = if array_arg_ind > n_input_arrays {
-                            "out".to_string()
-                        } else {
-                            format!("d{}", array_arg_ind - 1)
-                        };
-                        let Expr::Ident(ref bound_ident) = arg.ident else {
-                            panic!("")
-                        };
-                        bound_variable_string.push_str(&format!(
-                            "let {bound_ident} = {shape_vec_string}[{}];",
-                            array_dim_ind - 1
-                        ))
-                    }
-                    _ => panic!("Unexpected arg type in exec function."),
-                }
-            }
-
-            let output_shape_vec_string =
-                "let dout = std::slice::from_raw_parts(output.shape, output.ndim);";
-
-            let output_array_string =
-                "let out = std::slice::from_raw_parts_mut(output.data, dout.iter().product());";
-
             format!(
                 r#"
 #[no_mangle]
 unsafe extern "C"
-fn f(inputs: *const Tensor, n_inputs: usize, output: *mut TensorMut) {{
-    let inputs = std::slice::from_raw_parts(inputs, n_inputs);
-    let output = &mut *output;
-
-    {input_shape_vecs_string}
-    {output_shape_vec_string}
-
-    {input_arrays_string}
-    {output_array_string}
-
-    {bound_variable_string}
+fn f_workspace(inputs: *const Tensor, n_inputs: usize, outputs: *mut TensorMut, n_outputs: usize, workspace: *mut u8) {{
+    {preamble}
 
+    let mut workspace = workspace;
     {function_body}
 }}
 "#,
-                function_body = Self::render_block(&body),
+                preamble = Self::render_exec_preamble(args, true),
             )
         } else {
             panic!("Found non-`Function` `Statement` for executive function.")
         }
     }
 
+    fn render_len(shape: &[String]) -> String {
+        match shape.is_empty() {
+            true => "1".to_string(),
+            false => shape.join(" * "),
+        }
+    }
+
     fn render_statement(statement: &Statement) -> String {
         match statement {
             Statement::Assignment { left, right } => format!(
@@ -334,12 +598,41 @@ fn f(inputs: *const Tensor, n_inputs: usize, output: *mut TensorMut) {{
                 Self::render_expr(left),
                 Self::render_expr(right)
             ),
+            // loops run in order, so this is a plain accumulation
+            Statement::AtomicAdd { left, right, dtype } => {
+                let compute = dtype.compute();
+                Self::render_statement(&Statement::Assignment {
+                    left: left.clone(),
+                    right: Expr::Cast {
+                        value: Box::new(Expr::Op {
+                            op: Op::Add,
+                            inputs: vec![
+                                Expr::Cast {
+                                    value: Box::new(left.clone()),
+                                    from: *dtype,
+                                    to: compute,
+                                },
+                                Expr::Cast {
+                                    value: Box::new(right.clone()),
+                                    from: *dtype,
+                                    to: compute,
+                                },
+                            ],
+                        }),
+                        from: compute,
+                        to: *dtype,
+                    },
+                })
+            }
             Statement::Declaration {
                 ident,
                 value,
                 type_,
             } => {
-                let (Type::Int(mutable) | Type::Array(mutable) | Type::ArrayRef(mutable)) = type_;
+                let (Type::Int(mutable)
+                | Type::Scalar(mutable, _)
+                | Type::Array(mutable, _)
+                | Type::ArrayRef(mutable, _)) = type_;
                 format!(
                     "let {}{ident}: {} = {};",
                     if *mutable { "mut " } else { "" },
@@ -347,6 +640,11 @@ fn f(inputs: *const Tensor, n_inputs: usize, output: *mut TensorMut) {{
                     Self::render_expr(value)
                 )
             }
+            Statement::If { condition, body } => format!(
+                "if {} {{ {} }}",
+                Self::render_expr(condition),
+                Self::render_block(body)
+            ),
             Statement::Skip { index, bound } => format!("if {index} >= {bound} {{ continue; }}"),
             Statement::Loop {
                 index, bound, body, ..
@@ -361,7 +659,6 @@ fn f(inputs: *const Tensor, n_inputs: usize, output: *mut TensorMut) {{
                 "#[no_mangle]\nfn {ident}({}) {{{}}}",
                 args.iter()
                     .map(|Arg { type_, ident }| {
-                        let (Type::Int(_) | Type::Array(_) | Type::ArrayRef(_)) = type_;
                         format!("{}: {}", Self::render_expr(ident), Self::render_type(type_),)
                     })
                     .collect::<Vec<_>>()
@@ -1,22 +1,33 @@
 pub mod parser;
 
+use crate::ast::{DType, Op};
+
 #[derive(Clone, Debug)]
 pub enum Expr {
     Alloc {
-        initial_value: f32,
+        initial_value: f64,
+        dtype: DType,
         shape: Vec<String>,
     },
     Int(usize),
+    Literal(f64, DType), // an element of the given type
     Ident(String),
     Ref(String, bool), // like Ident(_), but a ref (and tracks mutability)
     Op {
-        op: char,
+        op: Op,
         inputs: Vec<Expr>,
     },
     Indexed {
         ident: String, // TODO: Should be Expr (Ident)
         index: Box<Expr>,
     },
+    // index arithmetic is cast from `DType::I32`
+    Cast {
+        value: Box<Expr>,
+        from: DType,
+        to: DType,
+    },
+    AsIndex(Box<Expr>), // a `DType::I32` element used in index arithmetic
 }
 
 // Should this be an Expr variant?
@@ -29,8 +40,9 @@ pub struct Arg {
 #[derive(Clone, Debug)]
 pub enum Type {
     Int(bool),
-    Array(bool),
-    ArrayRef(bool),
+    Scalar(bool, DType),
+    Array(bool, DType),
+    ArrayRef(bool, DType),
 }
 
 #[derive(Clone, Debug)]
@@ -44,6 +56,16 @@ pub enum Statement {
         value: Expr,
         type_: Type,
     },
+    If {
+        condition: Expr,
+        body: Block,
+    },
+    // like Assignment { left, right: left + right }, but other iterations may add to `left` at once
+    AtomicAdd {
+        left: Expr,
+        right: Expr,
+        dtype: DType,
+    },
     Skip {
         // TODO: These should both probably be Expr (Ident)
         index: String,
@@ -76,8 +98,8 @@ pub struct Block {
 
 #[derive(Clone, Debug)]
 pub struct Program {
-    pub rank: Statement,  // Should be `Statement::Function`
-    pub shape: Statement, // Should be `Statement::Function`
-    pub library: Block,   // Should consist only of `Statement::Function`s
-    pub exec: Statement,  // Should be `Statement::Function`
+    pub ranks: Vec<Statement>, // Should be `Statement::Function`s, one per output
+    pub shapes: Vec<Statement>, // Should be `Statement::Function`s, one per output
+    pub library: Block,        // Should consist only of `Statement::Function`s
+    pub exec: Statement,       // Should be `Statement::Function`
 }
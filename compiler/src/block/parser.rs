@@ -1,5 +1,6 @@
 use std::iter::Peekable;
 
+use crate::ast::{DType, Op};
 use crate::block::{Arg, Block, Expr, Program, Statement, Type};
 
 #[derive(Debug)]
@@ -14,15 +15,19 @@ pub fn parse(input: &str) -> Program {
     let sexp = parse_sexp(&mut iter);
     let mut block = parse_block_sexp(&sexp);
     // TODO This could check the `Statement`s are of variant `Function`...
-    let rank = block.statements.remove(0);
-    let shape = block.statements.remove(0);
     let exec = block.statements.pop().unwrap();
+    let is_named = |statement: &Statement, name: &str| matches!(statement, Statement::Function { ident, .. } if ident == name);
+    let (ranks, statements): (Vec<_>, Vec<_>) = block
+        .statements
+        .into_iter()
+        .partition(|statement| is_named(statement, "rank"));
+    let (shapes, statements): (Vec<_>, Vec<_>) = statements
+        .into_iter()
+        .partition(|statement| is_named(statement, "shape"));
     Program {
-        library: Block {
-            statements: block.statements,
-        },
-        rank,
-        shape,
+        library: Block { statements },
+        ranks,
+        shapes,
         exec,
     }
 }
@@ -55,6 +60,15 @@ fn parse_statement(sexp: &Sexp) -> Statement {
                         type_: parse_type(&list[2]),
                         value: parse_expr(&list[3]),
                     },
+                    "atomic-add" => Statement::AtomicAdd {
+                        dtype: parse_dtype(&parse_atom(&list[1])),
+                        left: parse_expr(&list[2]),
+                        right: parse_expr(&list[3]),
+                    },
+                    "if" => Statement::If {
+                        condition: parse_expr(&list[1]),
+                        body: parse_block_sexp(&list[2]),
+                    },
                     "skip" => Statement::Skip {
                         index: parse_atom(&list[1]),
                         bound: parse_atom(&list[2]),
@@ -130,9 +144,11 @@ fn parse_expr(sexp: &Sexp) -> Expr {
                 match a.as_str() {
                     "alloc" => {
                         let val = parse_float(&list[1]);
-                        let dims = parse_list_of_atoms(&list[2]);
+                        let dtype = parse_dtype(&parse_atom(&list[2]));
+                        let dims = list[3..].iter().map(parse_atom).collect();
                         Expr::Alloc {
                             initial_value: val,
+                            dtype,
                             shape: dims,
                         }
                     }
@@ -140,6 +156,9 @@ fn parse_expr(sexp: &Sexp) -> Expr {
                         let x = parse_atom(&list[1]).parse::<usize>().unwrap_or(0);
                         Expr::Int(x)
                     }
+                    "lit" => {
+                        Expr::Literal(parse_float(&list[1]), parse_dtype(&parse_atom(&list[2])))
+                    }
                     "id" => Expr::Ident(parse_atom(&list[1])),
                     "ref" => Expr::Ref(parse_atom(&list[1]), false),
                     "ref!" => Expr::Ref(parse_atom(&list[1]), true),
@@ -149,9 +168,8 @@ fn parse_expr(sexp: &Sexp) -> Expr {
                         for i in &list[2..] {
                             inputs.push(parse_expr(i));
                         }
-                        let op_char = op_atom.chars().next().unwrap_or('+');
                         Expr::Op {
-                            op: op_char,
+                            op: op_atom.parse::<Op>().unwrap_or(Op::Add),
                             inputs,
                         }
                     }
@@ -159,6 +177,12 @@ fn parse_expr(sexp: &Sexp) -> Expr {
                         ident: parse_atom(&list[1]),
                         index: Box::new(parse_expr(&list[2])),
                     },
+                    "cast" => Expr::Cast {
+                        from: parse_dtype(&parse_atom(&list[1])),
+                        to: parse_dtype(&parse_atom(&list[2])),
+                        value: Box::new(parse_expr(&list[3])),
+                    },
+                    "as-index" => Expr::AsIndex(Box::new(parse_expr(&list[1]))),
                     _ => Expr::Ident("".into()),
                 }
             } else {
@@ -171,13 +195,17 @@ fn parse_expr(sexp: &Sexp) -> Expr {
 
 fn parse_type(sexp: &Sexp) -> Type {
     if let Sexp::Atom(a) = sexp {
-        match a.as_str() {
+        let (kind, dtype) = a.split_once(':').unwrap_or((a, ""));
+        let dtype = parse_dtype(dtype);
+        match kind {
             "i" => Type::Int(false),
             "i!" => Type::Int(true),
-            "a" => Type::Array(false),
-            "a!" => Type::Array(true),
-            "ar" => Type::ArrayRef(false),
-            "ar!" => Type::ArrayRef(true),
+            "s" => Type::Scalar(false, dtype),
+            "s!" => Type::Scalar(true, dtype),
+            "a" => Type::Array(false, dtype),
+            "a!" => Type::Array(true, dtype),
+            "ar" => Type::ArrayRef(false, dtype),
+            "ar!" => Type::ArrayRef(true, dtype),
             _ => Type::Int(false),
         }
     } else {
@@ -185,6 +213,10 @@ fn parse_type(sexp: &Sexp) -> Type {
     }
 }
 
+fn parse_dtype(s: &str) -> DType {
+    s.parse::<DType>().unwrap_or_default()
+}
+
 fn parse_atom(sexp: &Sexp) -> String {
     match sexp {
         Sexp::Atom(s) => s.clone(),
@@ -192,16 +224,8 @@ fn parse_atom(sexp: &Sexp) -> String {
     }
 }
 
-fn parse_list_of_atoms(sexp: &Sexp) -> Vec<String> {
-    if let Sexp::Atom(s) = sexp {
-        s.split_whitespace().map(String::from).collect()
-    } else {
-        vec![]
-    }
-}
-
-fn parse_float(sexp: &Sexp) -> f32 {
-    parse_atom(sexp).parse::<f32>().unwrap_or(0.0)
+fn parse_float(sexp: &Sexp) -> f64 {
+    parse_atom(sexp).parse::<f64>().unwrap_or(0.0)
 }
 
 fn parse_sexp<I>(iter: &mut Peekable<I>) -> Sexp
@@ -0,0 +1,221 @@
+use std::fs::{self, File};
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::SystemTime;
+
+use crate::backend::{Build, BuildError, Render};
+use crate::block::Program;
+
+const DEFAULT_MAX_SIZE: u64 = 1 << 30;
+
+/// A directory of built libraries addressed by the program and backend they're built from, shared
+/// by every process that builds. Each entry is a `{key}.so` and the `{key}.key` text it was hashed
+/// from, compared on lookup so a hash collision only costs a rebuild. Entries are written to a
+/// temporary file then renamed in, so a reader never sees one half written.
+pub struct Cache {
+    dir: PathBuf,
+    max_size: u64, // in bytes, past which the least recently used entries are evicted
+}
+
+/// A library in the cache
+pub struct Entry {
+    pub key: String,
+    pub size: u64,
+    pub used: SystemTime,
+}
+
+impl Cache {
+    /// The cache in `$ILANG_CACHE_DIR`, else `$XDG_CACHE_HOME/ilang` or `~/.cache/ilang`, holding
+    /// up to `$ILANG_CACHE_SIZE` bytes (1 GiB by default)
+    pub fn from_env() -> Self {
+        let dir = match (
+            std::env::var_os("ILANG_CACHE_DIR"),
+            std::env::var_os("XDG_CACHE_HOME"),
+        ) {
+            (Some(dir), _) => PathBuf::from(dir),
+            (None, Some(dir)) => PathBuf::from(dir).join("ilang"),
+            (None, None) => PathBuf::from(std::env::var_os("HOME").unwrap_or_default())
+                .join(".cache")
+                .join("ilang"),
+        };
+        let max_size = std::env::var("ILANG_CACHE_SIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_MAX_SIZE);
+        Cache { dir, max_size }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn max_size(&self) -> u64 {
+        self.max_size
+    }
+
+    /// The library backend `B` builds from `program`, from the cache or built into it, passed to
+    /// `load` before it can be evicted
+    pub fn build<B: Render + Build, T>(
+        &self,
+        program: &Program,
+        load: impl FnOnce(&Path) -> Result<T, BuildError>,
+    ) -> Result<T, BuildError> {
+        let keyed = format!("{}\n{program:?}", B::fingerprint());
+        let key = format!("{:016x}", fnv1a(keyed.as_bytes()));
+        fs::create_dir_all(&self.dir)?;
+        if let Some((dylib_path, _lock)) = self.lookup(&key, &keyed)? {
+            return load(&dylib_path);
+        }
+
+        // one process builds a key while the others wait to find it
+        let key_lock = Self::lock_file(&self.path(&key, "lock"))?;
+        key_lock.lock()?;
+        if let Some((dylib_path, _lock)) = self.lookup(&key, &keyed)? {
+            return load(&dylib_path);
+        }
+        let dylib_path = self.path(&key, "so");
+        let dylib_temporary = Self::temporary(&dylib_path);
+        B::build(&B::render(program), &dylib_temporary)?;
+        let key_path = self.path(&key, "key");
+        let key_temporary = Self::temporary(&key_path);
+        fs::write(&key_temporary, keyed)?;
+
+        // renamed in and loaded under the shared lock, so no eviction removes it in between
+        let lock = self.lock()?;
+        lock.lock_shared()?;
+        fs::rename(dylib_temporary, &dylib_path)?;
+        // the key last, as it's what makes an entry found
+        fs::rename(key_temporary, key_path)?;
+        let loaded = load(&dylib_path)?;
+        drop(lock);
+
+        self.evict(&key)?;
+        Ok(loaded)
+    }
+
+    /// The entries, least recently used first
+    pub fn entries(&self) -> Result<Vec<Entry>, Error> {
+        let read_dir = match fs::read_dir(&self.dir) {
+            Ok(read_dir) => read_dir,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e),
+        };
+        let mut entries = vec![];
+        for file in read_dir {
+            let path = file?.path();
+            if path.extension().is_none_or(|extension| extension != "key") {
+                continue;
+            }
+            let key = path.file_stem().unwrap().to_string_lossy().into_owned();
+            let Ok(metadata) = fs::metadata(self.path(&key, "so")) else {
+                continue;
+            };
+            entries.push(Entry {
+                key,
+                size: metadata.len(),
+                used: metadata.modified()?,
+            });
+        }
+        entries.sort_by_key(|entry| entry.used);
+        Ok(entries)
+    }
+
+    /// Remove every entry, returning how many there were. Builds still running, whose files
+    /// aren't entries yet, are left to finish.
+    pub fn clear(&self) -> Result<usize, Error> {
+        if !self.dir.exists() {
+            return Ok(0);
+        }
+        let lock = self.lock()?;
+        lock.lock()?;
+        let entries = self.entries()?;
+        for entry in &entries {
+            self.remove(&entry.key)?;
+        }
+        Ok(entries.len())
+    }
+
+    /// The library of `key` if it was built from `keyed`, marked as used, with the shared lock
+    /// that keeps it from being removed until dropped
+    fn lookup(&self, key: &str, keyed: &str) -> Result<Option<(PathBuf, File)>, Error> {
+        let lock = self.lock()?;
+        lock.lock_shared()?;
+        let dylib_path = self.path(key, "so");
+        let found = fs::read_to_string(self.path(key, "key")).is_ok_and(|found| found == keyed);
+        if !found || !dylib_path.exists() {
+            return Ok(None);
+        }
+        File::options()
+            .write(true)
+            .open(&dylib_path)?
+            .set_modified(SystemTime::now())?;
+        Ok(Some((dylib_path, lock)))
+    }
+
+    /// Remove the least recently used entries until the rest fit in `max_size`, but for the
+    /// entry of `key`, which was just loaded
+    fn evict(&self, key: &str) -> Result<(), Error> {
+        let lock = self.lock()?;
+        lock.lock()?;
+        let entries = self.entries()?;
+        let mut size: u64 = entries.iter().map(|entry| entry.size).sum();
+        for entry in entries {
+            if size <= self.max_size {
+                break;
+            }
+            if entry.key == key {
+                continue;
+            }
+            self.remove(&entry.key)?;
+            size -= entry.size;
+        }
+        Ok(())
+    }
+
+    /// Remove the entry of `key` and its build lock, under the exclusive lock
+    fn remove(&self, key: &str) -> Result<(), Error> {
+        // the key first, so the entry is never found without its library
+        fs::remove_file(self.path(key, "key"))?;
+        fs::remove_file(self.path(key, "so"))?;
+        // at worst a process waiting on it builds the entry again, beside one that recreated it
+        match fs::remove_file(self.path(key, "lock")) {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    fn path(&self, key: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{key}.{extension}"))
+    }
+
+    /// The lock on the whole cache, shared to look up and load and exclusive to remove entries
+    fn lock(&self) -> Result<File, Error> {
+        Self::lock_file(&self.dir.join("lock"))
+    }
+
+    fn lock_file(path: &Path) -> Result<File, Error> {
+        File::options()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(path)
+    }
+
+    /// A path beside `path` for this call to write before renaming it to `path`, distinct from
+    /// those of other processes and of other threads of this one
+    fn temporary(path: &Path) -> PathBuf {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let count = COUNT.fetch_add(1, Ordering::Relaxed);
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(format!(".{}.{count}", std::process::id()));
+        PathBuf::from(temporary)
+    }
+}
+
+/// 64-bit FNV-1a, which is stable across builds unlike `DefaultHasher`
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
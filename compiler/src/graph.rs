@@ -3,18 +3,25 @@ use std::fmt::Write;
 use std::sync::{Arc, Mutex};
 
 use crate::ast::{
-    BinaryOp, Combinator, Expr, ExprBank, ExprRef, IndexExpr, NoOp, ScalarOp, Schedule, UnaryOp,
+    Combinator, DType, Expr, ExprBank, ExprRef, IndexExpr, NoOp, Op, ScalarOp, Schedule, Signature,
 };
+use crate::index::{self, Extent};
+
+mod autodiff;
 
 type NodeRef = Arc<Mutex<Node>>;
 
 #[derive(Clone, Debug)]
 pub enum NodeBody {
-    Leaf,
+    Leaf {
+        dtype: DType,
+        shape: Vec<Option<usize>>, // the static extent of each dimension, if known
+    },
     Interior {
-        op: char,
+        op: Op,
+        scan: Option<char>,
         schedule: Schedule,
-        shape: Vec<(usize, usize)>,
+        shape: Vec<Extent>,
     },
 }
 
@@ -33,6 +40,14 @@ impl Node {
             .map(|(child_ref, index)| (child_ref.lock().unwrap().clone(), index.clone()))
             .collect()
     }
+
+    /// The identity of each child, shared by all of its parents
+    pub fn child_ids(&self) -> Vec<usize> {
+        self.children
+            .iter()
+            .map(|(child_ref, _)| Arc::as_ptr(child_ref) as usize)
+            .collect()
+    }
 }
 
 fn get_parent_of_leftmost_leaf(node: &NodeRef) -> Option<NodeRef> {
@@ -69,42 +84,45 @@ fn get_leftmost_leaf(node_ref: &NodeRef) -> NodeRef {
 #[derive(Clone, Debug)]
 pub struct Graph {
     roots: Vec<NodeRef>,
+    inputs: Vec<NodeRef>, // the leaves in input order, if not as found from the roots
 }
 
 impl Graph {
     pub fn new() -> Self {
-        Self { roots: Vec::new() }
+        Self {
+            roots: Vec::new(),
+            inputs: Vec::new(),
+        }
     }
 
     pub fn deepcopy(&self) -> Self {
         fn copy_recursive(
             node_ref: &NodeRef,
-            visited: &mut HashMap<*const Node, NodeRef>,
+            visited: &mut HashMap<*const Mutex<Node>, NodeRef>,
         ) -> NodeRef {
-            let node = node_ref.lock().unwrap();
-            let ptr = &*node as *const Node;
+            let ptr = Arc::as_ptr(node_ref);
             if let Some(n) = visited.get(&ptr) {
                 return Arc::clone(n);
             }
 
-            let new_node = Arc::new(Mutex::new(Node {
-                index: node.index.clone(),
-                body: node.body.clone(),
-                parents: Vec::new(),
-                children: Vec::new(),
-            }));
+            // release the lock before recursing; the walk comes back through `parents`
+            let (new_node, children, parents) = {
+                let node = node_ref.lock().unwrap();
+                let new_node = Arc::new(Mutex::new(Node {
+                    index: node.index.clone(),
+                    body: node.body.clone(),
+                    parents: Vec::new(),
+                    children: Vec::new(),
+                }));
+                (new_node, node.children.clone(), node.parents.clone())
+            };
             visited.insert(ptr, Arc::clone(&new_node));
 
-            let children: Vec<_> = node
-                .children
+            let children: Vec<_> = children
                 .iter()
                 .map(|(c, idx)| (copy_recursive(c, visited), idx.clone()))
                 .collect();
-            let parents: Vec<_> = node
-                .parents
-                .iter()
-                .map(|p| copy_recursive(p, visited))
-                .collect();
+            let parents: Vec<_> = parents.iter().map(|p| copy_recursive(p, visited)).collect();
 
             let mut new_lock = new_node.lock().unwrap();
             new_lock.children = children;
@@ -119,7 +137,12 @@ impl Graph {
             .iter()
             .map(|r| copy_recursive(r, &mut visited))
             .collect();
-        Self { roots }
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|leaf| copy_recursive(leaf, &mut visited))
+            .collect();
+        Self { roots, inputs }
     }
 
     pub fn roots(&self) -> Vec<NodeRef> {
@@ -144,11 +167,15 @@ impl Graph {
 
     pub fn compose(&self, other: &Self) -> Self {
         let mut left = self.deepcopy();
-        let mut right = other.deepcopy();
+        let right = other.deepcopy();
+
+        let left_leaves = left.leaves();
+        let n_replaced = left_leaves.len().min(right.roots.len());
+        let inputs = [right.leaves(), left_leaves[n_replaced..].to_vec()].concat();
+        let explicit = !left.inputs.is_empty() || !right.inputs.is_empty();
 
         let mut r_iter = right.roots.into_iter();
-        let map: HashMap<usize, NodeRef> = left
-            .leaves()
+        let map: HashMap<usize, NodeRef> = left_leaves
             .into_iter()
             .filter_map(|leaf| {
                 r_iter
@@ -173,6 +200,12 @@ impl Graph {
         }
 
         left.roots.extend(r_iter);
+        // inputs given in order, like a gradient's, stay in it: those of `other`, then the leaves
+        // of `self` it doesn't feed
+        left.inputs = match explicit {
+            true => inputs,
+            false => vec![],
+        };
         left
     }
 
@@ -180,10 +213,15 @@ impl Graph {
         let mut left = self.deepcopy();
         let mut right = other.deepcopy();
 
-        let map: HashMap<usize, NodeRef> = right
-            .leaves()
+        let left_leaves = left.leaves();
+        let right_leaves = right.leaves();
+        let n_shared = left_leaves.len().min(right_leaves.len());
+        let inputs = [left_leaves.clone(), right_leaves[n_shared..].to_vec()].concat();
+        let explicit = !left.inputs.is_empty() || !right.inputs.is_empty();
+
+        let map: HashMap<usize, NodeRef> = right_leaves
             .into_iter()
-            .zip(left.leaves())
+            .zip(left_leaves)
             .map(|(r, l)| (Arc::as_ptr(&r) as usize, l))
             .collect();
 
@@ -203,6 +241,12 @@ impl Graph {
         }
 
         left.roots.extend(right.roots);
+        // inputs given in order stay in it: those of `self`, then the leaves of `other` it has
+        // beyond them
+        left.inputs = match explicit {
+            true => inputs,
+            false => vec![],
+        };
         left
     }
 
@@ -241,56 +285,49 @@ impl Graph {
         };
 
         match expr {
-            Expr::Index(IndexExpr { op, out, schedule }) => {
-                let children = match op {
-                    ScalarOp::BinaryOp(BinaryOp::Add(in0, in1))
-                    | ScalarOp::BinaryOp(BinaryOp::Sub(in0, in1))
-                    | ScalarOp::BinaryOp(BinaryOp::Mul(in0, in1))
-                    | ScalarOp::BinaryOp(BinaryOp::Div(in0, in1))
-                    | ScalarOp::BinaryOp(BinaryOp::Max(in0, in1)) => vec![
-                        (
-                            self.add_node(in0.0.clone(), NodeBody::Leaf, vec![], vec![]),
-                            in0.0.clone(),
-                        ),
-                        (
-                            self.add_node(in1.0.clone(), NodeBody::Leaf, vec![], vec![]),
-                            in1.0.clone(),
-                        ),
-                    ],
-                    ScalarOp::UnaryOp(UnaryOp::Accum(in0))
-                    | ScalarOp::UnaryOp(UnaryOp::Prod(in0))
-                    | ScalarOp::UnaryOp(UnaryOp::Relu(in0))
-                    | ScalarOp::UnaryOp(UnaryOp::Neg(in0))
-                    | ScalarOp::UnaryOp(UnaryOp::Recip(in0))
-                    | ScalarOp::UnaryOp(UnaryOp::Exp(in0))
-                    | ScalarOp::UnaryOp(UnaryOp::Log(in0))
-                    | ScalarOp::NoOp(NoOp(in0)) => {
-                        vec![(
-                            self.add_node(in0.0.clone(), NodeBody::Leaf, vec![], vec![]),
-                            in0.0.clone(),
-                        )]
-                    }
-                };
-                let op = match op {
-                    ScalarOp::UnaryOp(UnaryOp::Accum(_))
-                    | ScalarOp::BinaryOp(BinaryOp::Add(_, _)) => '+',
-                    ScalarOp::UnaryOp(UnaryOp::Prod(_))
-                    | ScalarOp::BinaryOp(BinaryOp::Mul(_, _)) => '*',
-                    ScalarOp::UnaryOp(UnaryOp::Relu(_))
-                    | ScalarOp::BinaryOp(BinaryOp::Max(_, _)) => '>',
-                    ScalarOp::UnaryOp(UnaryOp::Neg(_))
-                    | ScalarOp::BinaryOp(BinaryOp::Sub(_, _)) => '-',
-                    ScalarOp::UnaryOp(UnaryOp::Recip(_))
-                    | ScalarOp::BinaryOp(BinaryOp::Div(_, _)) => '/',
-                    ScalarOp::UnaryOp(UnaryOp::Exp(_)) => '^',
-                    ScalarOp::UnaryOp(UnaryOp::Log(_)) => '$',
-                    ScalarOp::NoOp(_) => ' ',
+            Expr::Index(IndexExpr {
+                op,
+                out,
+                scan,
+                schedule,
+            }) => {
+                let (op, inputs) = match op {
+                    ScalarOp::TernaryOp(op, in0, in1, in2) => (*op, vec![in0, in1, in2]),
+                    ScalarOp::BinaryOp(op, in0, in1) => (*op, vec![in0, in1]),
+                    ScalarOp::UnaryOp(op, in0) => (*op, vec![in0]),
+                    ScalarOp::NoOp(NoOp(in0)) => (Op::Id, vec![in0]),
                 };
+                let children: Vec<_> = inputs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(ind, input)| {
+                        // positions are integers
+                        let dtype = match op.position_input() {
+                            Some(position_ind) if position_ind == ind => DType::I32,
+                            _ => DType::default(),
+                        };
+                        let leaf = self.add_node(
+                            input.0.clone(),
+                            NodeBody::Leaf {
+                                dtype,
+                                shape: vec![None; index::dims(&input.0).len()],
+                            },
+                            vec![],
+                            vec![],
+                        );
+                        (leaf, input.0.clone())
+                    })
+                    .collect();
 
                 let body = NodeBody::Interior {
                     op,
+                    scan: *scan,
                     schedule: schedule.clone(),
-                    shape: infer_shape(&out.0, children.iter().map(|child| &child.1).collect()),
+                    shape: infer_shape(
+                        &out.0,
+                        &op,
+                        children.iter().map(|child| &child.1).collect(),
+                    ),
                 };
                 self.add_node(out.0.clone(), body, parents, children)
             }
@@ -312,7 +349,186 @@ impl Graph {
         }
     }
 
-    fn leaves(&self) -> Vec<NodeRef> {
+    /// Set the element type of each leaf, in input order
+    pub fn set_leaf_dtypes(&mut self, dtypes: &[DType]) -> Result<(), String> {
+        let leaves = self.leaves();
+        if leaves.len() != dtypes.len() {
+            return Err(format!(
+                "Expected {} dtypes, one per input, got {}",
+                leaves.len(),
+                dtypes.len()
+            ));
+        }
+        for (leaf, dtype) in leaves.iter().zip(dtypes) {
+            if let NodeBody::Leaf {
+                dtype: leaf_dtype, ..
+            } = &mut leaf.lock().unwrap().body
+            {
+                *leaf_dtype = *dtype;
+            }
+        }
+        Ok(())
+    }
+
+    /// Set the static shapes of the first leaves, in input order, from their signatures, which must
+    /// broadcast where the leaves are broadcastable
+    pub fn set_leaf_shapes(&mut self, signatures: &[Signature]) -> Result<(), String> {
+        let leaves = self.leaves();
+        if signatures.len() > leaves.len() {
+            return Err(format!(
+                "Expected at most {} signatures, one per input, got {}",
+                leaves.len(),
+                signatures.len()
+            ));
+        }
+        // checked in full before any is set
+        for (leaf, signature) in leaves.iter().zip(signatures) {
+            let leaf = leaf.lock().unwrap();
+            let rank = index::dims(&leaf.index).len();
+            if signature.shape.len() != rank {
+                return Err(format!(
+                    "Expected signature '{}' of rank {rank} for input [{}], got rank {}",
+                    signature.ident.0,
+                    leaf.index,
+                    signature.shape.len()
+                ));
+            }
+        }
+        let mut shapes = self.leaf_shapes();
+        for (shape, signature) in shapes.iter_mut().zip(signatures) {
+            *shape = signature.shape.clone();
+        }
+        self.check_broadcasts(&shapes)?;
+        for (leaf, signature) in leaves.iter().zip(signatures) {
+            if let NodeBody::Leaf { shape, .. } = &mut leaf.lock().unwrap().body {
+                *shape = signature.shape.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that every broadcastable dimension of an operand is 1 or as long as its index, given
+    /// the extents of the leaves' dimensions in input order, where known
+    pub fn check_broadcasts(&self, shapes: &[Vec<Option<usize>>]) -> Result<(), String> {
+        let leaves = self.leaves();
+        let mut memo = HashMap::new();
+        for root in &self.roots {
+            Self::node_extents(root, &leaves, shapes, &mut memo)?;
+        }
+        Ok(())
+    }
+
+    /// The extents of a node's dimensions where known, having checked its operands' broadcasts
+    fn node_extents(
+        node_ref: &NodeRef,
+        leaves: &[NodeRef],
+        shapes: &[Vec<Option<usize>>],
+        memo: &mut HashMap<usize, Vec<Option<usize>>>,
+    ) -> Result<Vec<Option<usize>>, String> {
+        let id = Arc::as_ptr(node_ref) as usize;
+        if let Some(extents) = memo.get(&id) {
+            return Ok(extents.clone());
+        }
+        let node = node_ref.lock().unwrap();
+        let extents = match &node.body {
+            NodeBody::Leaf { .. } => {
+                let input = leaves
+                    .iter()
+                    .position(|leaf| Arc::ptr_eq(leaf, node_ref))
+                    .unwrap();
+                shapes[input].clone()
+            }
+            NodeBody::Interior { op, shape, .. } => {
+                let child_extents = node
+                    .children
+                    .iter()
+                    .map(|(child, _)| Self::node_extents(child, leaves, shapes, memo))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let dim_extent = |child: usize, dim: usize| child_extents[child][dim];
+                let index_extents = infer_extents(
+                    &node.index,
+                    op,
+                    node.children.iter().map(|(_, index)| index).collect(),
+                );
+                for (child, (_, child_index)) in node.children.iter().enumerate() {
+                    for (dim, d) in index::dims(child_index).iter().enumerate() {
+                        let (true, Some(found)) = (d.broadcast, dim_extent(child, dim)) else {
+                            continue;
+                        };
+                        let c = d.terms[0].1;
+                        let extent = index_extents
+                            .get(&c)
+                            .and_then(|extent| extent.evaluate(&dim_extent));
+                        if let Some(extent) = extent.filter(|extent| found != 1 && found != *extent)
+                        {
+                            return Err(format!(
+                                "Expected dimension {dim} of [{child_index}] to be 1 or {extent}, the extent of index '{c}', got {found}"
+                            ));
+                        }
+                    }
+                }
+                shape
+                    .iter()
+                    .map(|extent| extent.evaluate(&dim_extent))
+                    .collect()
+            }
+        };
+        memo.insert(id, extents.clone());
+        Ok(extents)
+    }
+
+    /// The static extent of each dimension of each leaf, if known, in input order
+    pub fn leaf_shapes(&self) -> Vec<Vec<Option<usize>>> {
+        self.leaves()
+            .iter()
+            .map(|leaf| match &leaf.lock().unwrap().body {
+                NodeBody::Leaf { shape, .. } => shape.clone(),
+                NodeBody::Interior { .. } => unreachable!("Expected a leaf."),
+            })
+            .collect()
+    }
+
+    /// Set the element type of every leaf but those holding positions for data-dependent indexing,
+    /// which stay `DType::I32`
+    pub fn set_value_leaf_dtypes(&mut self, dtype: DType) {
+        let dtypes: Vec<DType> = self
+            .leaves()
+            .iter()
+            .map(|leaf| match self.is_position_leaf(leaf) {
+                true => DType::I32,
+                false => dtype,
+            })
+            .collect();
+        self.set_leaf_dtypes(&dtypes)
+            .expect("Expected one dtype per leaf.");
+    }
+
+    /// Whether a leaf is the position input of a data-dependent op
+    fn is_position_leaf(&self, leaf: &NodeRef) -> bool {
+        let mut seen = HashSet::new();
+        let mut stack = self.roots.clone();
+        while let Some(n) = stack.pop() {
+            if !seen.insert(Arc::as_ptr(&n) as usize) {
+                continue;
+            }
+            let node = n.lock().unwrap();
+            if let NodeBody::Interior { op, .. } = &node.body {
+                if let Some(ind) = op.position_input() {
+                    if Arc::ptr_eq(&node.children[ind].0, leaf) {
+                        return true;
+                    }
+                }
+            }
+            stack.extend(node.children.iter().map(|(c, _)| c.clone()));
+        }
+        false
+    }
+
+    /// The inputs in order: the leaves as first found from the roots, unless given
+    pub fn leaves(&self) -> Vec<NodeRef> {
+        if !self.inputs.is_empty() {
+            return self.inputs.clone();
+        }
         use std::collections::HashSet;
         let mut out = Vec::new();
         let mut seen = HashSet::new();
@@ -340,6 +556,50 @@ impl Graph {
         out
     }
 
+    /// The shape of each root, in terms of the shapes of the leaves in input order and their
+    /// static extents
+    pub fn shapes(&self) -> Vec<Vec<Extent>> {
+        let leaves = self.leaves();
+        self.roots
+            .iter()
+            .map(|root| Self::node_shape(root, &leaves))
+            .collect()
+    }
+
+    fn node_shape(node_ref: &NodeRef, leaves: &[NodeRef]) -> Vec<Extent> {
+        let node = node_ref.lock().unwrap();
+        match &node.body {
+            NodeBody::Leaf { shape, .. } => {
+                let input = leaves
+                    .iter()
+                    .position(|leaf| Arc::ptr_eq(leaf, node_ref))
+                    .unwrap();
+                shape
+                    .iter()
+                    .enumerate()
+                    .map(|(dim, static_extent)| match static_extent {
+                        Some(extent) => Extent::Sum {
+                            extents: vec![],
+                            offset: *extent as i64,
+                        },
+                        None => Extent::Dim(input, dim),
+                    })
+                    .collect()
+            }
+            NodeBody::Interior { shape, .. } => {
+                let child_shapes: Vec<Vec<Extent>> = node
+                    .children
+                    .iter()
+                    .map(|(child, _)| Self::node_shape(child, leaves))
+                    .collect();
+                shape
+                    .iter()
+                    .map(|extent| extent.substitute(&|child, dim| child_shapes[child][dim].clone()))
+                    .collect()
+            }
+        }
+    }
+
     pub fn to_dot(&self) -> String {
         let mut out = String::from("digraph G {\n");
         let mut visited = HashSet::new();
@@ -356,7 +616,7 @@ impl Graph {
             return;
         }
         let label = match &node.body {
-            NodeBody::Leaf => format!("{}", node.index),
+            NodeBody::Leaf { dtype, .. } => format!("{} {}", node.index, dtype),
             NodeBody::Interior { op, .. } => format!("{} {}", node.index, op),
         };
         writeln!(out, "\t{} [label=\"{}\"];", id, label).unwrap();
@@ -369,19 +629,93 @@ impl Graph {
     }
 }
 
-fn infer_shape(index: &String, child_indices: Vec<&String>) -> Vec<(usize, usize)> {
-    let index_map: HashMap<char, (usize, usize)> = child_indices
+fn infer_shape(index: &String, op: &Op, child_indices: Vec<&String>) -> Vec<Extent> {
+    let extents = infer_extents(index, op, child_indices);
+
+    // a merged output dimension is as long as its indices' together
+    let extent_of = |c: &char| match extents.get(c) {
+        Some(extent) => extent.clone(),
+        None => panic!("Could not infer the extent of index '{c}' of [{index}]."),
+    };
+    index::dims(index)
         .iter()
-        .enumerate()
-        .rev()
-        .flat_map(|(child_ind, child_index)| {
-            child_index
-                .chars()
-                .rev()
-                .enumerate()
-                .map(move |(char_ind, c)| (c, (child_ind, child_index.len() - 1 - char_ind)))
+        .map(|dim| match dim.plain() {
+            Some(c) => extent_of(&c),
+            None => Extent::Product(dim.terms.iter().map(|(_, c)| extent_of(c)).collect()),
         })
-        .collect();
+        .collect()
+}
 
-    index.chars().map(|c| index_map[&c]).collect()
+/// The extent of each index of a node, in terms of the dimensions of its operands
+fn infer_extents(index: &str, op: &Op, child_indices: Vec<&String>) -> HashMap<char, Extent> {
+    // plain dimensions give the extents of their indices (the first found), from which affine
+    // dimensions give the extents of the indices only found in them. those of an operand with a
+    // boundary mode take the whole dimension, e.g. `i` of `(i+k)@zero` is as long as it, and
+    // those of a slice or padding its window, e.g. `i` of `i[1:]` is one shorter. merged
+    // dimensions give their indices' extents as given, or divide out the others', e.g. `a` of
+    // `(ab:4)` is a quarter as long as it. an index only found broadcastable is as long as the
+    // longest of its dimensions.
+    let mut extents = HashMap::new();
+    let mut affine_dims = vec![];
+    let mut padded = vec![];
+    for (child_ind, child_index) in child_indices.iter().enumerate() {
+        for (dim_ind, dim) in index::dims(child_index).into_iter().enumerate() {
+            match dim.plain() {
+                Some(c) => {
+                    extents.entry(c).or_insert(Extent::Dim(child_ind, dim_ind));
+                }
+                None => {
+                    affine_dims.push((Extent::Dim(child_ind, dim_ind), dim));
+                    padded.push(index::boundary(child_index).is_some());
+                }
+            }
+        }
+    }
+    index::solve(&mut extents, &affine_dims, |ind, c, stride, terms| {
+        let (dim_extent, dim) = &affine_dims[ind];
+        if dim.broadcast {
+            return Extent::Max(
+                affine_dims
+                    .iter()
+                    .filter(|(_, dim)| dim.broadcast && dim.terms[0].1 == c)
+                    .map(|(extent, _)| extent.clone())
+                    .collect(),
+            );
+        }
+        match (&dim.merge, dim.window) {
+            (Some(merge), _) => match merge[dim.terms.iter().position(|(_, d)| *d == c).unwrap()] {
+                Some(extent) => Extent::Sum {
+                    extents: vec![],
+                    offset: extent as i64,
+                },
+                None => Extent::Quotient {
+                    dim: Box::new(dim_extent.clone()),
+                    divisors: terms.into_iter().map(|(_, extent)| extent).collect(),
+                },
+            },
+            (None, Some(window)) => {
+                let (extents, offset) = window.extent(dim_extent.clone());
+                Extent::Sum { extents, offset }
+            }
+            (None, None) => Extent::Solved {
+                dim: Box::new(dim_extent.clone()),
+                stride,
+                terms: match padded[ind] {
+                    true => vec![],
+                    false => terms,
+                },
+            },
+        }
+    });
+    // a concatenation is as long as its inputs together
+    if *op == Op::Concat {
+        let (c, first, second) = index::concatenated(index, child_indices[0], child_indices[1]);
+        let extents_of = |c: char| extents[&c].clone();
+        let concatenated = Extent::Sum {
+            extents: vec![extents_of(first), extents_of(second)],
+            offset: 0,
+        };
+        extents.insert(c, concatenated);
+    }
+    extents
 }
@@ -0,0 +1,667 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use super::{infer_shape, Graph, Node, NodeBody, NodeRef};
+use crate::ast::{DType, Op, Schedule};
+use crate::index;
+
+/// A node read at an index, as the operand of another
+type Operand = (NodeRef, String);
+
+impl Graph {
+    /// Differentiate the roots in reverse mode with respect to the inputs `wrt`, numbered in
+    /// input order. The gradient takes the inputs of `self` followed by a seed for each root, the
+    /// gradient of whatever it feeds, and has a root for each of `wrt` in order. Operands must be
+    /// indexed by distinct plain indices, and scans and data-dependent ops are not differentiable.
+    pub fn grad(&self, wrt: &[usize]) -> Result<Graph, String> {
+        let graph = self.deepcopy();
+        let leaves = graph.leaves();
+
+        let seeds: Vec<NodeRef> = graph
+            .roots
+            .iter()
+            .map(|root| {
+                let index = root.lock().unwrap().index.clone();
+                Self::leaf(&index)
+            })
+            .collect();
+
+        // the adjoints of a node are summed once those of all its parents are found, which
+        // reverse topological order guarantees
+        let mut adjoints: HashMap<usize, Vec<Operand>> = HashMap::new();
+        for (root, seed) in graph.roots.iter().zip(&seeds) {
+            let index = seed.lock().unwrap().index.clone();
+            adjoints
+                .entry(Arc::as_ptr(root) as usize)
+                .or_default()
+                .push((Arc::clone(seed), index));
+        }
+        for node_ref in graph.topological_order().iter().rev() {
+            let Some(node_adjoints) = adjoints.get(&(Arc::as_ptr(node_ref) as usize)) else {
+                continue;
+            };
+            let dy = Self::sum(node_adjoints.clone());
+            for (child, adjoint) in Self::operand_adjoints(node_ref, dy)? {
+                adjoints
+                    .entry(Arc::as_ptr(&child) as usize)
+                    .or_default()
+                    .push(adjoint);
+            }
+        }
+
+        // a root is stored to its own output, so a leaf or shared gradient is copied to one
+        let mut roots: Vec<NodeRef> = vec![];
+        for ind in wrt {
+            let leaf = leaves.get(*ind).ok_or_else(|| {
+                format!(
+                    "Expected an input below {} to differentiate with respect to, got {ind}",
+                    leaves.len()
+                )
+            })?;
+            let Some(leaf_adjoints) = adjoints.get(&(Arc::as_ptr(leaf) as usize)) else {
+                return Err(format!(
+                    "Expected the output to depend on input {ind} to differentiate with respect to it"
+                ));
+            };
+            let (gradient, index) = Self::sum(leaf_adjoints.clone());
+            let copy = roots.iter().any(|root| Arc::ptr_eq(root, &gradient))
+                || matches!(gradient.lock().unwrap().body, NodeBody::Leaf { .. });
+            roots.push(match copy {
+                true => Self::node(Op::Id, &index, vec![(gradient, index.clone())]),
+                false => gradient,
+            });
+        }
+
+        Ok(Graph {
+            roots,
+            inputs: [leaves, seeds].concat(),
+        })
+    }
+
+    /// Differentiate the roots in forward mode. The Jacobian-vector product takes the inputs of
+    /// `self` followed by a tangent for each, and has the roots of `self` followed by the tangent
+    /// of each, so both are found in one program. Scans other than `+` are not differentiable.
+    pub fn jvp(&self) -> Result<Graph, String> {
+        let graph = self.deepcopy();
+        let leaves = graph.leaves();
+
+        let tangent_leaves: Vec<NodeRef> = leaves
+            .iter()
+            .map(|leaf| {
+                let leaf = leaf.lock().unwrap();
+                Arc::new(Mutex::new(Node {
+                    index: leaf.index.clone(),
+                    body: leaf.body.clone(),
+                    parents: vec![],
+                    children: vec![],
+                }))
+            })
+            .collect();
+
+        // the tangent of a node is found once those of its children are, in topological order,
+        // and has the node's shape so it's read at the same operand indices
+        let mut tangents: HashMap<usize, NodeRef> = leaves
+            .iter()
+            .zip(&tangent_leaves)
+            .map(|(leaf, tangent)| (Arc::as_ptr(leaf) as usize, Arc::clone(tangent)))
+            .collect();
+        for node_ref in graph.topological_order() {
+            let ptr = Arc::as_ptr(&node_ref) as usize;
+            if !tangents.contains_key(&ptr) {
+                let tangent = Self::tangent(&node_ref, &tangents)?;
+                tangents.insert(ptr, tangent);
+            }
+        }
+
+        // a root is stored to its own output, so a leaf or shared tangent is copied to one
+        let mut roots = graph.roots.clone();
+        for root in &graph.roots {
+            let tangent = Arc::clone(&tangents[&(Arc::as_ptr(root) as usize)]);
+            let copy = roots.iter().any(|root| Arc::ptr_eq(root, &tangent))
+                || matches!(tangent.lock().unwrap().body, NodeBody::Leaf { .. });
+            roots.push(match copy {
+                true => {
+                    let rank = index::dims(&tangent.lock().unwrap().index).len();
+                    let index: String = ('a'..='z').take(rank).collect();
+                    Self::node(Op::Id, &index, vec![(tangent, index.clone())])
+                }
+                false => tangent,
+            });
+        }
+
+        Ok(Graph {
+            roots,
+            inputs: [leaves, tangent_leaves].concat(),
+        })
+    }
+
+    /// The nodes reachable from the roots, each after all of its children
+    fn topological_order(&self) -> Vec<NodeRef> {
+        fn visit(node_ref: &NodeRef, seen: &mut HashSet<usize>, order: &mut Vec<NodeRef>) {
+            if !seen.insert(Arc::as_ptr(node_ref) as usize) {
+                return;
+            }
+            let children = node_ref.lock().unwrap().children.clone();
+            for (child, _) in &children {
+                visit(child, seen, order);
+            }
+            order.push(Arc::clone(node_ref));
+        }
+        let mut seen = HashSet::new();
+        let mut order = vec![];
+        for root in &self.roots {
+            visit(root, &mut seen, &mut order);
+        }
+        order
+    }
+
+    /// The adjoint of each operand of the node `y` given its own, `dy`, each indexed like the
+    /// operand. A reduction spreads `dy` over its operand, and an operand found with fewer
+    /// indices than `y` (broadcast) gets `dy` summed over the others.
+    fn operand_adjoints(y: &NodeRef, dy: Operand) -> Result<Vec<(NodeRef, Operand)>, String> {
+        let (out, op, scan, children) = {
+            let node = y.lock().unwrap();
+            let NodeBody::Interior { op, scan, .. } = &node.body else {
+                return Ok(vec![]);
+            };
+            (node.index.clone(), *op, *scan, node.children.clone())
+        };
+        let distinct_plain = |index: &str| {
+            index::is_plain(index) && index::chars(index).len() == index::dims(index).len()
+        };
+        if scan.is_some() || matches!(op, Op::Gather | Op::Scatter | Op::Concat) {
+            return Err(format!(
+                "Expected neither a scan nor op [{op}] to differentiate [{out}]"
+            ));
+        }
+        if !distinct_plain(&out) || !children.iter().all(|(_, index)| distinct_plain(index)) {
+            return Err(format!(
+                "Expected [{out}] and its operands to be indexed by distinct plain indices to differentiate it"
+            ));
+        }
+        let reducing = children.len() == 1
+            && index::chars(&children[0].1)
+                .iter()
+                .any(|c| !out.contains(*c));
+        let in_output = children
+            .iter()
+            .all(|(_, index)| index::chars(index).iter().all(|c| out.contains(*c)));
+        if !reducing && !in_output {
+            return Err(format!(
+                "Expected the indices of the operands of [{out}] to differentiate in its output"
+            ));
+        }
+
+        // `dy` is read at the output index, and renamed to it
+        let dy = (dy.0, out.clone());
+        let y = (Arc::clone(y), out.clone());
+        let x: Vec<Operand> = children.clone();
+        let at = |op: Op, operands: Vec<Operand>| (Self::node(op, &out, operands), out.clone());
+        let neg = |value: Operand| at(Op::Sub, vec![value]);
+        // `value` where `mask` holds and zero elsewhere, even where `value` isn't finite
+        let masked = |mask: Operand, value: Operand| {
+            let zero = at(Op::Zeros, vec![value.clone()]);
+            at(Op::Select, vec![mask, value, zero])
+        };
+
+        // each adjoint is found at the output index, then summed down to the operand's
+        let adjoints: Vec<Option<Operand>> = match (op, x.len()) {
+            // spread over the reduced indices, at the operand's index
+            (Op::Add | Op::Mul | Op::Max | Op::Min, 1) if reducing => {
+                let index = x[0].1.clone();
+                let adjoint = match op {
+                    Op::Add => Self::broadcast(&dy, &x[0]),
+                    Op::Mul => {
+                        let others = Self::product_of_others(&x[0], &index, &out);
+                        (Self::node(Op::Mul, &index, vec![dy, others]), index)
+                    }
+                    _ => Self::split_between_ties(&dy, &x[0], &y, &index, &out),
+                };
+                return Ok(vec![(Arc::clone(&x[0].0), adjoint)]);
+            }
+            (Op::Id | Op::Add | Op::Mul, 1) => vec![Some(dy)],
+            (Op::Sub, 1) => vec![Some(neg(dy))],
+            (Op::Div, 1) => {
+                let y2 = at(Op::Mul, vec![y.clone(), y]);
+                vec![Some(neg(at(Op::Mul, vec![dy, y2])))]
+            }
+            // relu passes where `y` is positive, i.e. above its negation; min(x, 0) below
+            (Op::Max | Op::Min, 1) => {
+                let compare = if op == Op::Max { Op::Gt } else { Op::Lt };
+                let mask = at(compare, vec![y.clone(), neg(y)]);
+                vec![Some(masked(mask, dy))]
+            }
+            (Op::Exp, 1) => vec![Some(at(Op::Mul, vec![dy, y]))],
+            (Op::Log, 1) => vec![Some(at(Op::Div, vec![dy, x[0].clone()]))],
+            (Op::Abs, 1) => {
+                let neg_x = neg(x[0].clone());
+                let positive = at(Op::Gt, vec![x[0].clone(), neg_x.clone()]);
+                let negative = at(Op::Lt, vec![x[0].clone(), neg_x]);
+                vec![Some(at(
+                    Op::Sub,
+                    vec![masked(positive, dy.clone()), masked(negative, dy)],
+                ))]
+            }
+            (Op::Sqrt, 1) => {
+                let twice_y = at(Op::Add, vec![y.clone(), y]);
+                vec![Some(at(Op::Div, vec![dy, twice_y]))]
+            }
+            // -y / 2x
+            (Op::Rsqrt, 1) => {
+                let twice_x = at(Op::Add, vec![x[0].clone(), x[0].clone()]);
+                let scale = at(Op::Div, vec![y, twice_x]);
+                vec![Some(neg(at(Op::Mul, vec![dy, scale])))]
+            }
+            // dy - dy y²
+            (Op::Tanh, 1) => {
+                let y2 = at(Op::Mul, vec![y.clone(), y]);
+                let dy_y2 = at(Op::Mul, vec![dy.clone(), y2]);
+                vec![Some(at(Op::Sub, vec![dy, dy_y2]))]
+            }
+            // dy y - dy y²
+            (Op::Sigmoid, 1) => {
+                let dy_y = at(Op::Mul, vec![dy, y.clone()]);
+                let dy_y2 = at(Op::Mul, vec![dy_y.clone(), y]);
+                vec![Some(at(Op::Sub, vec![dy_y, dy_y2]))]
+            }
+            (Op::Sin, 1) => {
+                let cos = at(Op::Cos, vec![x[0].clone()]);
+                vec![Some(at(Op::Mul, vec![dy, cos]))]
+            }
+            (Op::Cos, 1) => {
+                let sin = at(Op::Sin, vec![x[0].clone()]);
+                vec![Some(neg(at(Op::Mul, vec![dy, sin])))]
+            }
+            (Op::Add, 2) => vec![Some(dy.clone()), Some(dy)],
+            (Op::Sub, 2) => vec![Some(dy.clone()), Some(neg(dy))],
+            (Op::Mul, 2) => vec![
+                Some(at(Op::Mul, vec![dy.clone(), x[1].clone()])),
+                Some(at(Op::Mul, vec![dy, x[0].clone()])),
+            ],
+            (Op::Div, 2) => {
+                let dy_y = at(Op::Mul, vec![dy.clone(), y]);
+                vec![
+                    Some(at(Op::Div, vec![dy, x[1].clone()])),
+                    Some(neg(at(Op::Div, vec![dy_y, x[1].clone()]))),
+                ]
+            }
+            // ties go to the first operand
+            (Op::Max | Op::Min, 2) => {
+                let (first, second) = match op {
+                    Op::Max => (Op::Ge, Op::Lt),
+                    _ => (Op::Le, Op::Gt),
+                };
+                vec![
+                    Some(masked(
+                        at(first, vec![x[0].clone(), x[1].clone()]),
+                        dy.clone(),
+                    )),
+                    Some(masked(at(second, vec![x[0].clone(), x[1].clone()]), dy)),
+                ]
+            }
+            // dy b y / a, and dy y log(a)
+            (Op::Pow, 2) => {
+                let dy_y = at(Op::Mul, vec![dy, y]);
+                let exponent = at(Op::Mul, vec![dy_y.clone(), x[1].clone()]);
+                let log = at(Op::Log, vec![x[0].clone()]);
+                vec![
+                    Some(at(Op::Div, vec![exponent, x[0].clone()])),
+                    Some(at(Op::Mul, vec![dy_y, log])),
+                ]
+            }
+            // each branch where it is picked, zero elsewhere
+            (Op::Select, 3) => {
+                let zero = at(Op::Zeros, vec![dy.clone()]);
+                vec![
+                    None,
+                    Some(at(Op::Select, vec![x[0].clone(), dy.clone(), zero.clone()])),
+                    Some(at(Op::Select, vec![x[0].clone(), zero, dy])),
+                ]
+            }
+            // piecewise constant, or not numbers
+            _ => vec![],
+        };
+
+        let adjoints = x
+            .into_iter()
+            .zip(adjoints)
+            .filter_map(|((child, index), adjoint)| {
+                let (value, _) = adjoint?;
+                let adjoint = match index == out {
+                    true => (value, index),
+                    // summed over the indices the operand lacks, or only reordered
+                    false => {
+                        let op = match index.len() == out.len() {
+                            true => Op::Id,
+                            false => Op::Add,
+                        };
+                        (Self::node(op, &index, vec![(value, out.clone())]), index)
+                    }
+                };
+                Some((child, adjoint))
+            })
+            .collect();
+        Ok(adjoints)
+    }
+
+    /// The tangent of the interior node `y` given those of its children, indexed like `y`. Ops
+    /// linear in their (non-position, non-condition) operands apply to the tangents in their
+    /// place; the rest find theirs over all the node's indices, then sum or reorder it to `y`'s.
+    fn tangent(y: &NodeRef, tangents: &HashMap<usize, NodeRef>) -> Result<NodeRef, String> {
+        let (out, op, scan, children) = {
+            let node = y.lock().unwrap();
+            let NodeBody::Interior { op, scan, .. } = &node.body else {
+                unreachable!("Expected an interior node to find the tangent of.")
+            };
+            (node.index.clone(), *op, *scan, node.children.clone())
+        };
+        let x: Vec<Operand> = children;
+        let dx: Vec<Operand> = x
+            .iter()
+            .map(|(child, index)| {
+                let tangent = &tangents[&(Arc::as_ptr(child) as usize)];
+                (Arc::clone(tangent), index.clone())
+            })
+            .collect();
+        if scan.is_some() && op != Op::Add {
+            return Err(format!(
+                "Expected a scan with op [+] to differentiate [{out}], got [{op}]"
+            ));
+        }
+
+        let reducing = x.len() == 1
+            && index::chars(&x[0].1)
+                .iter()
+                .any(|c| !index::chars(&out).contains(c));
+        match (op, x.len()) {
+            (Op::Id | Op::Add | Op::Sub | Op::Concat, _) => return Ok(Self::with_operands(y, dx)),
+            (Op::Mul, 1) if !reducing => return Ok(Self::with_operands(y, dx)),
+            (Op::Gather, _) => {
+                let operands = vec![dx[0].clone(), x[1].clone()];
+                return Ok(Self::with_operands(y, operands));
+            }
+            (Op::Scatter, 3) => {
+                let operands = vec![dx[0].clone(), x[1].clone(), dx[2].clone()];
+                return Ok(Self::with_operands(y, operands));
+            }
+            // the ones added don't vary
+            (Op::Scatter, _) => return Ok(Self::node(Op::Id, &out, vec![dx[0].clone()])),
+            (Op::Select, _) => {
+                let operands = vec![x[0].clone(), dx[1].clone(), dx[2].clone()];
+                return Ok(Self::with_operands(y, operands));
+            }
+            _ => {}
+        }
+
+        // the output's indices, then the operands' it lacks
+        let mut all = index::chars(&out);
+        for (_, index) in &x {
+            for c in index::chars(index) {
+                if !all.contains(&c) {
+                    all.push(c);
+                }
+            }
+        }
+        let all: String = all.into_iter().collect();
+        let at = |op: Op, operands: Vec<Operand>| (Self::node(op, &all, operands), all.clone());
+        let neg = |value: Operand| at(Op::Sub, vec![value]);
+        // zeros over every operand, as a tangent can't be multiplied by a zero if not finite
+        let zero = || at(Op::Zeros, x.clone());
+        let masked = |mask: Operand, value: Operand| at(Op::Select, vec![mask, value, zero()]);
+        let distinct_plain =
+            index::is_plain(&out) && index::chars(&out).len() == index::dims(&out).len();
+        // `y` itself where its output index is readable, or found again over all the indices
+        let y_at = || match distinct_plain {
+            true => (Arc::clone(y), out.clone()),
+            false => at(op, x.clone()),
+        };
+
+        let tangent: Operand = match (op, x.len()) {
+            // Σ dx times the product of the others
+            (Op::Mul, 1) => {
+                let others = Self::product_of_others(&x[0], &all, &out);
+                at(Op::Mul, vec![dx[0].clone(), others])
+            }
+            // the mean tangent of the elements picked
+            (Op::Max | Op::Min, 1) if reducing => {
+                if !distinct_plain {
+                    return Err(format!(
+                        "Expected [{out}] to be indexed by distinct plain indices to differentiate op [{op}]"
+                    ));
+                }
+                Self::split_between_ties(&dx[0], &x[0], &y_at(), &all, &out)
+            }
+            (Op::Div, 1) => {
+                let y = y_at();
+                let y2 = at(Op::Mul, vec![y.clone(), y]);
+                neg(at(Op::Mul, vec![dx[0].clone(), y2]))
+            }
+            (Op::Max | Op::Min, 1) => {
+                let y = y_at();
+                let compare = if op == Op::Max { Op::Gt } else { Op::Lt };
+                let mask = at(compare, vec![y.clone(), neg(y)]);
+                masked(mask, dx[0].clone())
+            }
+            (Op::Exp, 1) => at(Op::Mul, vec![dx[0].clone(), y_at()]),
+            (Op::Log, 1) => at(Op::Div, vec![dx[0].clone(), x[0].clone()]),
+            (Op::Abs, 1) => {
+                let neg_x = neg(x[0].clone());
+                let positive = at(Op::Gt, vec![x[0].clone(), neg_x.clone()]);
+                let negative = at(Op::Lt, vec![x[0].clone(), neg_x]);
+                at(
+                    Op::Sub,
+                    vec![
+                        masked(positive, dx[0].clone()),
+                        masked(negative, dx[0].clone()),
+                    ],
+                )
+            }
+            (Op::Sqrt, 1) => {
+                let y = y_at();
+                let twice_y = at(Op::Add, vec![y.clone(), y]);
+                at(Op::Div, vec![dx[0].clone(), twice_y])
+            }
+            // -dx y / 2x
+            (Op::Rsqrt, 1) => {
+                let twice_x = at(Op::Add, vec![x[0].clone(), x[0].clone()]);
+                let scale = at(Op::Div, vec![y_at(), twice_x]);
+                neg(at(Op::Mul, vec![dx[0].clone(), scale]))
+            }
+            // dx - dx y²
+            (Op::Tanh, 1) => {
+                let y = y_at();
+                let y2 = at(Op::Mul, vec![y.clone(), y]);
+                let dx_y2 = at(Op::Mul, vec![dx[0].clone(), y2]);
+                at(Op::Sub, vec![dx[0].clone(), dx_y2])
+            }
+            // dx y - dx y²
+            (Op::Sigmoid, 1) => {
+                let y = y_at();
+                let dx_y = at(Op::Mul, vec![dx[0].clone(), y.clone()]);
+                let dx_y2 = at(Op::Mul, vec![dx_y.clone(), y]);
+                at(Op::Sub, vec![dx_y, dx_y2])
+            }
+            (Op::Sin, 1) => {
+                let cos = at(Op::Cos, vec![x[0].clone()]);
+                at(Op::Mul, vec![dx[0].clone(), cos])
+            }
+            (Op::Cos, 1) => {
+                let sin = at(Op::Sin, vec![x[0].clone()]);
+                neg(at(Op::Mul, vec![dx[0].clone(), sin]))
+            }
+            (Op::Mul, 2) => at(
+                Op::Add,
+                vec![
+                    at(Op::Mul, vec![dx[0].clone(), x[1].clone()]),
+                    at(Op::Mul, vec![x[0].clone(), dx[1].clone()]),
+                ],
+            ),
+            // (da - db y) / b
+            (Op::Div, 2) => {
+                let db_y = at(Op::Mul, vec![dx[1].clone(), y_at()]);
+                let difference = at(Op::Sub, vec![dx[0].clone(), db_y]);
+                at(Op::Div, vec![difference, x[1].clone()])
+            }
+            // ties go to the first operand
+            (Op::Max | Op::Min, 2) => {
+                let (first, second) = match op {
+                    Op::Max => (Op::Ge, Op::Lt),
+                    _ => (Op::Le, Op::Gt),
+                };
+                at(
+                    Op::Add,
+                    vec![
+                        masked(at(first, x.clone()), dx[0].clone()),
+                        masked(at(second, x.clone()), dx[1].clone()),
+                    ],
+                )
+            }
+            // y (da b / a + db log(a))
+            (Op::Pow, 2) => {
+                let da_b = at(Op::Mul, vec![dx[0].clone(), x[1].clone()]);
+                let exponent = at(Op::Div, vec![da_b, x[0].clone()]);
+                let log = at(Op::Log, vec![x[0].clone()]);
+                let base = at(Op::Mul, vec![dx[1].clone(), log]);
+                at(Op::Mul, vec![y_at(), at(Op::Add, vec![exponent, base])])
+            }
+            // piecewise constant, or not numbers
+            _ => zero(),
+        };
+
+        // summed over the indices the output lacks, or only reordered
+        Ok(match (tangent.1 == out, reducing) {
+            (true, _) => tangent.0,
+            (false, true) => Self::node(Op::Add, &out, vec![tangent]),
+            (false, false) => Self::node(Op::Id, &out, vec![tangent]),
+        })
+    }
+
+    /// A node like the interior node `y` computing the same op of `operands` instead
+    fn with_operands(y: &NodeRef, operands: Vec<Operand>) -> NodeRef {
+        let (index, body) = {
+            let node = y.lock().unwrap();
+            (node.index.clone(), node.body.clone())
+        };
+        let node = Arc::new(Mutex::new(Node {
+            index,
+            body,
+            parents: vec![],
+            children: operands,
+        }));
+        for (child, _) in node.lock().unwrap().children.iter() {
+            child.lock().unwrap().parents.push(Arc::clone(&node));
+        }
+        node
+    }
+
+    /// The product of the elements of `x` reduced into each element of `out` but the one at
+    /// `index`, which has the indices of both, without dividing by a zero: a zero's is the
+    /// product of the rest, and the others' are zero with one
+    fn product_of_others(x: &Operand, index: &str, out: &str) -> Operand {
+        let at = |op: Op, operands: Vec<Operand>| (Self::node(op, index, operands), index.into());
+        let at_out = |op: Op, operands: Vec<Operand>| (Self::node(op, out, operands), out.into());
+        let zero = at(Op::Zeros, vec![x.clone()]);
+        let one = at(Op::Ones, vec![x.clone()]);
+        let is_zero = at(Op::Eq, vec![x.clone(), zero.clone()]);
+        let nonzero = at(Op::Select, vec![is_zero.clone(), one.clone(), x.clone()]);
+
+        let product = at_out(Op::Mul, vec![nonzero.clone()]);
+        let count = at(Op::Select, vec![is_zero.clone(), one.clone(), zero.clone()]);
+        let n_zeros = at_out(Op::Add, vec![count]);
+        let no_other_zero = at(
+            Op::Select,
+            vec![
+                is_zero,
+                at(Op::Eq, vec![n_zeros.clone(), one]),
+                at(Op::Eq, vec![n_zeros, zero]),
+            ],
+        );
+        at(
+            Op::Mul,
+            vec![at(Op::Div, vec![product, nonzero]), no_other_zero],
+        )
+    }
+
+    /// `value`, read at `out`, split evenly between the elements of `x` at `index` that tie for
+    /// the extreme `y` they're reduced into there, and zero for the rest
+    fn split_between_ties(
+        value: &Operand,
+        x: &Operand,
+        y: &Operand,
+        index: &str,
+        out: &str,
+    ) -> Operand {
+        let at = |op: Op, operands: Vec<Operand>| (Self::node(op, index, operands), index.into());
+        let zero = at(Op::Zeros, vec![x.clone()]);
+        let one = at(Op::Ones, vec![x.clone()]);
+        let picked = at(Op::Eq, vec![x.clone(), y.clone()]);
+        let count = at(Op::Select, vec![picked.clone(), one.clone(), zero.clone()]);
+        let n_picked = (Self::node(Op::Add, out, vec![count]), out.to_string());
+        let share = at(Op::Div, vec![one, n_picked]);
+        let value = at(Op::Mul, vec![value.clone(), share]);
+        at(Op::Select, vec![picked, value, zero])
+    }
+
+    /// `dy` repeated across the indices of `x` it lacks, times ones with the extents of `x`
+    fn broadcast(dy: &Operand, x: &Operand) -> Operand {
+        let index = x.1.clone();
+        let ones = (Self::node(Op::Ones, &index, vec![x.clone()]), index.clone());
+        (Self::node(Op::Mul, &index, vec![dy.clone(), ones]), index)
+    }
+
+    /// The sum of `operands`, indexed like the first
+    fn sum(operands: Vec<Operand>) -> Operand {
+        let mut operands = operands.into_iter();
+        let first = operands.next().expect("Expected an operand to sum.");
+        operands.fold(first, |(sum, index), (operand, _)| {
+            let node = Self::node(
+                Op::Add,
+                &index,
+                vec![(sum, index.clone()), (operand, index.clone())],
+            );
+            (node, index)
+        })
+    }
+
+    /// A new input indexed by `index`
+    fn leaf(index: &str) -> NodeRef {
+        Arc::new(Mutex::new(Node {
+            index: index.to_string(),
+            body: NodeBody::Leaf {
+                dtype: DType::default(),
+                shape: vec![None; index::dims(index).len()],
+            },
+            parents: vec![],
+            children: vec![],
+        }))
+    }
+
+    /// A new node computing `op` of `operands` at `index`
+    fn node(op: Op, index: &str, operands: Vec<Operand>) -> NodeRef {
+        let index = index.to_string();
+        let body = NodeBody::Interior {
+            op,
+            scan: None,
+            schedule: Schedule {
+                splits: HashMap::new(),
+                loop_order: vec![],
+                compute_levels: vec![],
+            },
+            shape: infer_shape(
+                &index,
+                &op,
+                operands.iter().map(|(_, index)| index).collect(),
+            ),
+        };
+        let node = Arc::new(Mutex::new(Node {
+            index,
+            body,
+            parents: vec![],
+            children: operands,
+        }));
+        for (child, _) in node.lock().unwrap().children.iter() {
+            child.lock().unwrap().parents.push(Arc::clone(&node));
+        }
+        node
+    }
+}
@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// A dimension of an operand index: the sum of its (coefficient, index) terms and a constant
+/// offset. A plain index `i` is `[(1, 'i')]` + 0, `(2i+k-1)` is `[(2, 'i'), (1, 'k')]` - 1. A
+/// sliced `i[2:]` or padded `i{1,1}` index spans a window of the dimension rather than all of it.
+/// A merged dimension `(ab)` instead flattens its terms' indices, outermost first, with `merge`
+/// holding any of their extents given like `(ab:4)`. A broadcastable index `i?` may be 1 where
+/// the index is longer, and is then read at 0 throughout.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Dim {
+    pub terms: Vec<(usize, char)>,
+    pub offset: i64,
+    pub window: Option<Window>,
+    pub merge: Option<Vec<Option<usize>>>,
+    pub broadcast: bool,
+}
+
+/// The positions `start..end` of a dimension an index spans, read from offset `start`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub start: i64,
+    pub end: WindowEnd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowEnd {
+    At(usize),
+    FromEnd(i64), // relative to the extent of the dimension
+}
+
+impl Dim {
+    /// The index of a plain dimension
+    pub fn plain(&self) -> Option<char> {
+        match (&self.terms[..], self.offset, self.window, self.broadcast) {
+            ([(1, c)], 0, None, false) => Some(*c),
+            _ => None,
+        }
+    }
+
+    /// Whether the window of the dimension reaches outside of it
+    pub fn padded(&self) -> bool {
+        match self.window {
+            Some(Window { start, end }) => start < 0 || matches!(end, WindowEnd::FromEnd(1..)),
+            None => false,
+        }
+    }
+}
+
+impl Window {
+    /// The extent of the window onto a dimension of extent `dim`, as the extents and constant
+    /// it sums
+    pub fn extent<T>(&self, dim: T) -> (Vec<T>, i64) {
+        match self.end {
+            WindowEnd::At(end) => (vec![], end as i64 - self.start),
+            WindowEnd::FromEnd(end) => (vec![dim], end - self.start),
+        }
+    }
+}
+
+/// How an operand is read where an affine dimension falls outside its extent
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Boundary {
+    Clamp,  // the nearest edge element
+    Wrap,   // from the opposite edge
+    Zero,   // 0
+    Mirror, // reflected about the edge element
+}
+
+impl FromStr for Boundary {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(Boundary::Clamp),
+            "wrap" => Ok(Boundary::Wrap),
+            "zero" => Ok(Boundary::Zero),
+            "mirror" => Ok(Boundary::Mirror),
+            _ => Err(format!("Unknown boundary mode '{s}'")),
+        }
+    }
+}
+
+/// Parse an operand index like `ij`, `(i+k)(j+l)`, `(2i+k)`, `(i+k-1)@zero`, `i[1:-1]`,
+/// `i{2,2}@clamp`, `(ab:4)` or `i?j` into its dimensions and boundary mode, or describe what was
+/// expected
+pub fn parse(index: &str) -> Result<(Vec<Dim>, Option<Boundary>), String> {
+    let (dims_str, boundary) = match index.split_once('@') {
+        Some((dims_str, mode)) => (
+            dims_str,
+            Some(
+                mode.parse::<Boundary>()
+                    .map_err(|_| format!("a boundary mode in index '{index}'"))?,
+            ),
+        ),
+        None => (index, None),
+    };
+    let mut dims = vec![];
+    let mut chars = dims_str.chars().peekable();
+    let integer = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        let negative = chars.next_if_eq(&'-').is_some();
+        let mut digits = String::new();
+        while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+            digits.push(d);
+        }
+        let value = digits.parse::<i64>().ok()?;
+        Some(if negative { -value } else { value })
+    };
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_alphabetic() => {
+                let broadcast = chars.next_if_eq(&'?').is_some();
+                let window = match chars.next_if(|d| !broadcast && (*d == '[' || *d == '{')) {
+                    // a slice `[start:end]`, with a negative end counting back from the extent
+                    Some('[') => {
+                        let start = integer(&mut chars).unwrap_or(0);
+                        let separator = chars.next();
+                        let end = match integer(&mut chars) {
+                            Some(end) if end < 0 => Some(WindowEnd::FromEnd(end)),
+                            Some(end) if end >= start => Some(WindowEnd::At(end as usize)),
+                            Some(_) => None,
+                            None => Some(WindowEnd::FromEnd(0)),
+                        };
+                        match (start >= 0, separator, end, chars.next()) {
+                            (true, Some(':'), Some(end), Some(']')) => Some(Window { start, end }),
+                            _ => return Err(format!("a slice like '[2:10]' in index '{index}'")),
+                        }
+                    }
+                    // padding `{before,after}`
+                    Some(_) => {
+                        let before = integer(&mut chars);
+                        let separator = chars.next();
+                        let after = integer(&mut chars);
+                        match (before, separator, after, chars.next()) {
+                            (Some(before @ 0..), Some(','), Some(after @ 0..), Some('}')) => {
+                                Some(Window {
+                                    start: -before,
+                                    end: WindowEnd::FromEnd(after),
+                                })
+                            }
+                            _ => return Err(format!("padding like '{{1,1}}' in index '{index}'")),
+                        }
+                    }
+                    None => None,
+                };
+                dims.push(Dim {
+                    terms: vec![(1, c)],
+                    offset: window.map_or(0, |window| window.start),
+                    window,
+                    merge: None,
+                    broadcast,
+                })
+            }
+            '(' => {
+                let mut dim = Dim {
+                    terms: vec![],
+                    offset: 0,
+                    window: None,
+                    merge: None,
+                    broadcast: false,
+                };
+                // a merged dimension lists two or more indices without operators
+                let inner: String = chars.clone().take_while(|c| *c != ')').collect();
+                if !inner.contains(['+', '-'])
+                    && inner.chars().filter(|c| c.is_alphabetic()).count() > 1
+                {
+                    let mut extents = vec![];
+                    while let Some(c) = chars.next_if(|c| c.is_alphabetic()) {
+                        dim.terms.push((1, c));
+                        extents.push(match chars.next_if_eq(&':') {
+                            Some(_) => match integer(&mut chars) {
+                                Some(extent @ 1..) => Some(extent as usize),
+                                _ => return Err(format!("an extent after ':' in index '{index}'")),
+                            },
+                            None => None,
+                        });
+                    }
+                    if chars.next() != Some(')') {
+                        return Err(format!("an index or ')' in index '{index}'"));
+                    }
+                    dim.merge = Some(extents);
+                    dims.push(dim);
+                    continue;
+                }
+                let mut negative = false;
+                loop {
+                    let mut digits = String::new();
+                    while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                        digits.push(d);
+                    }
+                    let value = match digits.as_str() {
+                        "" => None,
+                        s => Some(
+                            s.parse::<usize>()
+                                .map_err(|_| format!("a coefficient in index '{index}'"))?,
+                        ),
+                    };
+                    match (chars.next_if(|c| c.is_alphabetic()), value) {
+                        (Some(c), value) if !negative && value != Some(0) => {
+                            dim.terms.push((value.unwrap_or(1), c))
+                        }
+                        (None, Some(value)) => match negative {
+                            true => dim.offset -= value as i64,
+                            false => dim.offset += value as i64,
+                        },
+                        _ => return Err(format!("a term in index '{index}'")),
+                    }
+                    match chars.next() {
+                        Some('+') => negative = false,
+                        Some('-') => negative = true,
+                        Some(')') => break,
+                        _ => return Err(format!("'+', '-' or ')' in index '{index}'")),
+                    }
+                }
+                if dim.terms.is_empty() {
+                    return Err(format!("an index in each dimension of index '{index}'"));
+                }
+                dims.push(dim);
+            }
+            _ => return Err(format!("an index or '(' in index '{index}', found '{c}'")),
+        }
+    }
+    if boundary.is_none()
+        && dims
+            .iter()
+            .any(|dim| dim.window.is_none() && dim.offset != 0)
+    {
+        return Err(format!(
+            "a boundary mode for the offsets of index '{index}'"
+        ));
+    }
+    // padding is zeros unless another mode is given
+    let boundary = match dims.iter().any(|dim| dim.padded()) {
+        true => boundary.or(Some(Boundary::Zero)),
+        false => boundary,
+    };
+    Ok((dims, boundary))
+}
+
+/// The dimensions of an index that's already been through the parser
+pub fn dims(index: &str) -> Vec<Dim> {
+    parse(index).unwrap_or_else(|e| panic!("Expected {e}.")).0
+}
+
+/// The boundary mode of an index that's already been through the parser
+pub fn boundary(index: &str) -> Option<Boundary> {
+    parse(index).unwrap_or_else(|e| panic!("Expected {e}.")).1
+}
+
+/// The unique indices of an operand index, in order of appearance
+pub fn chars(index: &str) -> Vec<char> {
+    let mut chars: Vec<char> = vec![];
+    for dim in dims(index) {
+        for (_, c) in dim.terms {
+            if !chars.contains(&c) {
+                chars.push(c);
+            }
+        }
+    }
+    chars
+}
+
+/// Whether every dimension of an operand index is a single index
+pub fn is_plain(index: &str) -> bool {
+    dims(index).iter().all(|dim| dim.plain().is_some())
+}
+
+/// The indices of a concatenation like `concat ij kj~nj`: the output's found in neither input,
+/// then the first's and the second's not in the output
+pub fn concatenated(index: &str, first: &str, second: &str) -> (char, char, char) {
+    let (first_chars, second_chars) = (chars(first), chars(second));
+    let only = |chars: &[char]| -> Vec<char> {
+        chars
+            .iter()
+            .filter(|c| !index.contains(**c))
+            .copied()
+            .collect()
+    };
+    let joined: Vec<char> = chars(index)
+        .into_iter()
+        .filter(|c| !first_chars.contains(c) && !second_chars.contains(c))
+        .collect();
+    let rank = chars(index).len();
+    match (&joined[..], &only(&first_chars)[..], &only(&second_chars)[..]) {
+        ([c], [first_c], [second_c])
+            if first_c != second_c && first_chars.len() == rank && second_chars.len() == rank =>
+        {
+            (*c, *first_c, *second_c)
+        }
+        _ => panic!(
+            "Expected one index of [{index}] to concatenate along, and one of each input to concatenate."
+        ),
+    }
+}
+
+/// The size of a dimension, in terms of the dimensions of a set of arrays
+#[derive(Clone, Debug, PartialEq)]
+pub enum Extent {
+    Dim(usize, usize), // array, dimension
+    /// The extent of an index with coefficient `stride` in a dimension `dim` whose other `terms`
+    /// (coefficient, extent) are known: `(dim - 1 - Σ coefficient * (extent - 1)) / stride + 1`
+    Solved {
+        dim: Box<Extent>,
+        stride: usize,
+        terms: Vec<(usize, Extent)>,
+    },
+    Sum {
+        extents: Vec<Extent>,
+        offset: i64,
+    },
+    Product(Vec<Extent>),
+    Max(Vec<Extent>),
+    Quotient {
+        dim: Box<Extent>,
+        divisors: Vec<Extent>,
+    },
+}
+
+impl Extent {
+    /// Replace the array dimensions of `self` with extents from `f`
+    pub fn substitute(&self, f: &impl Fn(usize, usize) -> Extent) -> Extent {
+        match self {
+            Extent::Dim(array, dim) => f(*array, *dim),
+            Extent::Solved { dim, stride, terms } => Extent::Solved {
+                dim: Box::new(dim.substitute(f)),
+                stride: *stride,
+                terms: terms
+                    .iter()
+                    .map(|(coefficient, extent)| (*coefficient, extent.substitute(f)))
+                    .collect(),
+            },
+            Extent::Sum { extents, offset } => Extent::Sum {
+                extents: extents.iter().map(|extent| extent.substitute(f)).collect(),
+                offset: *offset,
+            },
+            Extent::Product(extents) => {
+                Extent::Product(extents.iter().map(|extent| extent.substitute(f)).collect())
+            }
+            Extent::Max(extents) => {
+                Extent::Max(extents.iter().map(|extent| extent.substitute(f)).collect())
+            }
+            Extent::Quotient { dim, divisors } => Extent::Quotient {
+                dim: Box::new(dim.substitute(f)),
+                divisors: divisors.iter().map(|extent| extent.substitute(f)).collect(),
+            },
+        }
+    }
+
+    /// The value of `self` given the array dimensions from `f`, if they're all known
+    pub fn evaluate(&self, f: &impl Fn(usize, usize) -> Option<usize>) -> Option<usize> {
+        let all = |extents: &[Extent]| -> Option<Vec<usize>> {
+            extents.iter().map(|extent| extent.evaluate(f)).collect()
+        };
+        Some(match self {
+            Extent::Dim(array, dim) => f(*array, *dim)?,
+            Extent::Solved { dim, stride, terms } => {
+                let mut span = dim.evaluate(f)? as i64 - 1;
+                for (coefficient, extent) in terms {
+                    span -= *coefficient as i64 * (extent.evaluate(f)? as i64 - 1);
+                }
+                (span.div_euclid(*stride as i64) + 1).max(0) as usize
+            }
+            Extent::Sum { extents, offset } => {
+                (all(extents)?.iter().sum::<usize>() as i64 + offset).max(0) as usize
+            }
+            Extent::Product(extents) => all(extents)?.iter().product(),
+            Extent::Max(extents) => all(extents)?.into_iter().max()?,
+            Extent::Quotient { dim, divisors } => {
+                dim.evaluate(f)? / all(divisors)?.iter().product::<usize>().max(1)
+            }
+        })
+    }
+}
+
+/// Extend the `extents` of plain indices to the indices only found in compound dimensions, by
+/// solving each of the `dims` (extent, dimension) for its one index of unknown extent until no
+/// more can be, or for an index of a merged dimension given its extent. `solve` is passed the
+/// position of the dimension in `dims`, the unknown index, its coefficient and the remaining
+/// (coefficient, extent) terms of known extent.
+pub fn solve<T: Clone>(
+    extents: &mut HashMap<char, T>,
+    dims: &[(T, Dim)],
+    mut solve: impl FnMut(usize, char, usize, Vec<(usize, T)>) -> T,
+) {
+    loop {
+        let solvable = dims.iter().enumerate().find_map(|(ind, (_, dim))| {
+            let unknown: Vec<(usize, &(usize, char))> = dim
+                .terms
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, c))| !extents.contains_key(c))
+                .collect();
+            let given = |(position, _): &&(usize, &(usize, char))| {
+                dim.merge
+                    .as_ref()
+                    .is_some_and(|merge| merge[*position].is_some())
+            };
+            match (unknown.iter().find(given), &unknown[..]) {
+                (Some((_, (stride, c))), _) | (None, [(_, (stride, c))]) => {
+                    Some((ind, *stride, *c, dim))
+                }
+                _ => None,
+            }
+        });
+        let Some((ind, stride, c, dim)) = solvable else {
+            return;
+        };
+        let terms = dim
+            .terms
+            .iter()
+            .filter(|(_, d)| *d != c)
+            .filter_map(|(coefficient, d)| Some((*coefficient, extents.get(d)?.clone())))
+            .collect();
+        let solved = solve(ind, c, stride, terms);
+        extents.insert(c, solved);
+    }
+}
@@ -1,7 +1,11 @@
 pub mod ast;
 pub mod backend;
 pub mod block;
+pub mod cache;
 pub mod graph;
+pub mod half;
+pub mod index;
 pub mod lowerer;
 pub mod parser;
+pub mod tempdir;
 pub mod tokenizer;
@@ -1,15 +1,21 @@
 use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 
-use crate::ast::Schedule;
+use crate::ast::{DType, Op, Schedule};
 use crate::block::{Arg, Block, Expr, Program, Statement, Type};
 use crate::graph::{Graph, Node, NodeBody};
+use crate::index::{self, Boundary, Dim, Extent};
 
 pub struct Lowerer {
     input_args: Vec<Arg>,
+    output_args: Vec<Arg>,
+    outputs: HashMap<usize, String>, // the store ident of each root, by node
     input_array_counter: usize,
     base_loop_counter: usize,
     store_counter: usize,
     split_factor_count: usize,
+    wide_accumulation: bool,
+    static_bounds: HashMap<String, usize>, // the bound idents of dimensions of static extent
 }
 
 #[derive(Clone, Debug)]
@@ -20,85 +26,228 @@ struct Lowered {
     def_args: Vec<Arg>, // only populated for kernel fragemnts, empty for full kernels
     loop_idents: HashMap<char, (String, String)>,
     store_ident: String,
-    shape: Vec<(usize, usize)>,
+    dim_bounds: Vec<String>, // bound ident of each dimension of the store
+    dtype: DType,
 }
 
 impl Lowerer {
     pub fn new() -> Self {
         Lowerer {
             input_args: Vec::new(),
+            output_args: Vec::new(),
+            outputs: HashMap::new(),
             input_array_counter: 0,
             base_loop_counter: 0,
             store_counter: 0,
             split_factor_count: 0,
+            wide_accumulation: false,
+            static_bounds: HashMap::new(),
         }
     }
 
+    /// Accumulate reductions (`+ijk~ij`, `*ijk~ij`) in a scalar of the wider
+    /// `DType::accumulator` type, written back to the store once the innermost reduction loops
+    /// finish. Unscheduled reductions get their reduction loops ordered innermost.
+    pub fn wide_accumulation(mut self, wide_accumulation: bool) -> Self {
+        self.wide_accumulation = wide_accumulation;
+        self
+    }
+
     fn get_char_indices(index: &String) -> Vec<char> {
         let mut seen = HashSet::new();
         index.chars().filter(|c| seen.insert(*c)).collect()
     }
 
     pub fn lower(&mut self, graph: &Graph) -> Program {
-        assert_eq!(
-            graph.roots().len(),
-            1,
-            "Attempted to lower `Graph` of {} roots.",
-            graph.roots().len()
-        );
+        let roots = graph.roots();
+        assert!(!roots.is_empty(), "Attempted to lower `Graph` of no roots.");
+
+        // each root is stored in an output array, `out` when it is the only one
+        self.outputs = roots
+            .iter()
+            .enumerate()
+            .map(|(ind, root)| {
+                let ident = match roots.len() {
+                    1 => "out".to_string(),
+                    _ => format!("out{ind}"),
+                };
+                (Arc::as_ptr(root) as usize, ident)
+            })
+            .collect();
 
+        // leaves are lowered first, so that inputs are numbered in the order of `Graph::leaves`
         let mut memo = HashMap::<usize, Lowered>::new();
-        let lowered = self.lower_node(
-            &graph.root().lock().unwrap(),
-            HashSet::new(),
-            true,
-            &mut memo,
-        );
+        for leaf in graph.leaves() {
+            let id = Arc::as_ptr(&leaf) as usize;
+            self.lower_node(&leaf.lock().unwrap(), id, HashSet::new(), &mut memo);
+        }
+        let lowered: Vec<Lowered> = roots
+            .iter()
+            .map(|root| {
+                let id = Arc::as_ptr(root) as usize;
+                self.lower_node(&root.lock().unwrap(), id, HashSet::new(), &mut memo)
+            })
+            .collect();
 
-        //let lowered = self.lower_node(&graph.root().lock().unwrap(), HashSet::new(), true);
-        Program {
-            rank: Statement::Function {
-                ident: "rank".to_string(),
-                args: vec![],
-                body: Block {
-                    statements: vec![Statement::Return {
-                        value: Expr::Int(lowered.shape.len()),
-                    }],
-                },
+        // inputs precede outputs, and an arg reduction's extreme values follow its positions
+        let args = [self.input_args.clone(), self.output_args.clone()].concat();
+        let shapes: Vec<Vec<Extent>> = roots
+            .iter()
+            .zip(graph.shapes())
+            .flat_map(|(root, shape)| {
+                let n = match root.lock().unwrap().body {
+                    NodeBody::Interior {
+                        op: Op::ArgMax | Op::ArgMin,
+                        ..
+                    } => 2,
+                    _ => 1,
+                };
+                vec![shape; n]
+            })
+            .collect();
+        let mut program = Program {
+            ranks: shapes
+                .iter()
+                .map(|shape| Statement::Function {
+                    ident: "rank".to_string(),
+                    args: vec![],
+                    body: Block {
+                        statements: vec![Statement::Return {
+                            value: Expr::Int(shape.len()),
+                        }],
+                    },
+                })
+                .collect(),
+            shapes: shapes
+                .iter()
+                .map(|shape| Statement::Function {
+                    ident: "shape".to_string(),
+                    args: args.clone(),
+                    body: Block {
+                        statements: shape
+                            .iter()
+                            .enumerate()
+                            .map(|(ind, extent)| Statement::Assignment {
+                                left: Expr::Indexed {
+                                    ident: format!("shape"),
+                                    index: Box::new(Expr::Int(ind)),
+                                },
+                                right: Self::create_extent_expr(extent),
+                            })
+                            .collect(),
+                    },
+                })
+                .collect(),
+            library: Block {
+                statements: lowered
+                    .iter()
+                    .flat_map(|lowered| lowered.def_block.statements.clone())
+                    .collect(),
             },
-            shape: Statement::Function {
-                ident: "shape".to_string(),
-                args: self.input_args.clone(),
+            exec: Statement::Function {
+                ident: "f".to_string(),
+                args,
                 body: Block {
                     statements: lowered
-                        .shape
-                        .iter()
-                        .enumerate()
-                        .map(|(ind, (input_ind, dim))| Statement::Assignment {
-                            left: Expr::Indexed {
-                                ident: format!("shape"),
-                                index: Box::new(Expr::Int(ind)),
-                            },
-                            right: Expr::Indexed {
-                                ident: format!("d{input_ind}"),
-                                index: Box::new(Expr::Int(*dim)),
-                            },
+                        .into_iter()
+                        .flat_map(|lowered| {
+                            [
+                                lowered.alloc_block.statements,
+                                lowered.exec_block.statements,
+                            ]
+                            .concat()
                         })
                         .collect(),
                 },
             },
-            library: lowered.def_block,
-            exec: Statement::Function {
-                ident: "f".to_string(),
-                args: self.input_args.clone(),
-                body: Block {
-                    statements: [
-                        lowered.alloc_block.statements,
-                        lowered.exec_block.statements,
-                    ]
-                    .concat(),
-                },
-            },
+        };
+
+        // specialize to static shapes: the exec function declares the bounds derived from them
+        // that the library then uses
+        let mut static_bounds = self.static_bounds.clone();
+        if let Statement::Function { body, .. } = &mut program.exec {
+            Self::specialize_block(body, &mut static_bounds);
+        }
+        Self::specialize_block(&mut program.library, &mut static_bounds);
+        program
+    }
+
+    /// Replace the bounds of static extent in `block` with their values, extending
+    /// `static_bounds` to those declared from them alone
+    fn specialize_block(block: &mut Block, static_bounds: &mut HashMap<String, usize>) {
+        for statement in block.statements.iter_mut() {
+            match statement {
+                Statement::Assignment { left, right }
+                | Statement::AtomicAdd { left, right, .. } => {
+                    Self::specialize_expr(left, static_bounds);
+                    Self::specialize_expr(right, static_bounds);
+                }
+                Statement::Declaration {
+                    ident,
+                    value,
+                    type_,
+                } => {
+                    Self::specialize_expr(value, static_bounds);
+                    if let (Type::Int(_), Some(extent)) = (type_, Self::evaluate(value)) {
+                        static_bounds.insert(ident.clone(), extent);
+                    }
+                }
+                Statement::If { condition, body } => {
+                    Self::specialize_expr(condition, static_bounds);
+                    Self::specialize_block(body, static_bounds);
+                }
+                Statement::Loop { bound, body, .. } => {
+                    Self::specialize_expr(bound, static_bounds);
+                    Self::specialize_block(body, static_bounds);
+                }
+                Statement::Return { value } => Self::specialize_expr(value, static_bounds),
+                Statement::Function { body, .. } => Self::specialize_block(body, static_bounds),
+                Statement::Skip { .. } | Statement::Call { .. } => {}
+            }
+        }
+    }
+
+    fn specialize_expr(expr: &mut Expr, static_bounds: &HashMap<String, usize>) {
+        match expr {
+            Expr::Ident(ident) => {
+                if let Some(extent) = static_bounds.get(ident.as_str()) {
+                    *expr = Expr::Int(*extent);
+                }
+            }
+            Expr::Op { inputs, .. } => {
+                for input in inputs.iter_mut() {
+                    Self::specialize_expr(input, static_bounds);
+                }
+            }
+            Expr::Indexed { index, .. } => Self::specialize_expr(index, static_bounds),
+            Expr::Cast { value, .. } | Expr::AsIndex(value) => {
+                Self::specialize_expr(value, static_bounds)
+            }
+            Expr::Alloc { .. } | Expr::Int(_) | Expr::Literal(..) | Expr::Ref(..) => {}
+        }
+    }
+
+    /// The value of an index arithmetic expression of constants
+    fn evaluate(expr: &Expr) -> Option<usize> {
+        match expr {
+            Expr::Int(value) => Some(*value),
+            Expr::Op { op, inputs } => {
+                let values = inputs
+                    .iter()
+                    .map(Self::evaluate)
+                    .collect::<Option<Vec<usize>>>()?;
+                let (first, rest) = values.split_first()?;
+                rest.iter().try_fold(*first, |value, other| match op {
+                    Op::Add => value.checked_add(*other),
+                    Op::Sub => value.checked_sub(*other),
+                    Op::Mul => value.checked_mul(*other),
+                    Op::Div => value.checked_div(*other),
+                    Op::Max => Some(value.max(*other)),
+                    Op::Min => Some(value.min(*other)),
+                    _ => None,
+                })
+            }
+            _ => None,
         }
     }
 
@@ -106,38 +255,33 @@ impl Lowerer {
     fn lower_node(
         &mut self,
         node: &Node,
+        id: usize,
         pruned_loops: HashSet<(char, usize)>,
-        root: bool,
         memo: &mut HashMap<usize, Lowered>,
     ) -> Lowered {
-        let id = node as *const _ as usize;
-
+        // a node shared by several parents is computed once, into the store they all read
         if let Some(cached) = memo.get(&id) {
             return Lowered {
-                def_block: Block {
-                    statements: Vec::new(),
-                },
-                exec_block: Block {
-                    statements: Vec::new(),
-                },
+                def_block: Block::default(),
+                alloc_block: Block::default(),
+                exec_block: Block::default(),
                 ..cached.clone()
             };
         }
 
         let lowered = match &node.body {
-            NodeBody::Leaf => self.lower_leaf_node(&node.index),
+            NodeBody::Leaf { dtype, shape } => self.lower_leaf_node(&node.index, *dtype, shape),
             NodeBody::Interior {
-                op,
-                schedule,
-                shape,
+                op, scan, schedule, ..
             } => self.lower_interior_node(
                 &node.index,
                 &op,
+                *scan,
                 &node.children(),
-                shape,
+                &node.child_ids(),
                 &schedule,
                 pruned_loops,
-                root,
+                self.outputs.get(&id).cloned(),
                 memo,
             ),
         };
@@ -148,34 +292,53 @@ impl Lowerer {
     }
 
     /// Return function def block, alloc block, exec block, (bound, iterator) ident map, store ident
-    fn lower_leaf_node(&mut self, index: &String) -> Lowered {
+    fn lower_leaf_node(
+        &mut self,
+        index: &str,
+        dtype: DType,
+        static_shape: &[Option<usize>],
+    ) -> Lowered {
         let arg_ident = format!("in{}", self.input_array_counter);
         self.input_array_counter += 1;
 
-        let char_indices = Self::get_char_indices(index);
-
-        let loop_idents: HashMap<char, (String, String)> = char_indices
-            .iter()
-            .map(|char_index| {
-                let bound_ident = format!("b{}", self.base_loop_counter);
-                let iterator_ident = format!("i{}", self.base_loop_counter);
-                self.base_loop_counter += 1;
-                (*char_index, (bound_ident, iterator_ident))
-            })
-            .collect();
+        // each dimension has a bound, shared by the repeats of a plain index, which also gets a
+        // loop; the indices of affine dimensions get theirs from the consumer. those of static
+        // extent are specialized to it once lowered.
+        let dims = index::dims(index);
+        let mut loop_idents: HashMap<char, (String, String)> = HashMap::new();
+        let mut dim_bounds = vec![];
+        let mut dim_args = vec![];
+        for (dim, static_extent) in dims.iter().zip(static_shape) {
+            let bound_ident = match dim.plain() {
+                Some(c) if loop_idents.contains_key(&c) => loop_idents[&c].0.clone(),
+                plain => {
+                    let bound_ident = format!("b{}", self.base_loop_counter);
+                    if let Some(extent) = static_extent {
+                        self.static_bounds.insert(bound_ident.clone(), *extent);
+                    }
+                    if let Some(c) = plain {
+                        let iterator_ident = format!("i{}", self.base_loop_counter);
+                        loop_idents.insert(c, (bound_ident.clone(), iterator_ident));
+                    }
+                    self.base_loop_counter += 1;
+                    dim_args.push(Arg {
+                        type_: Type::Int(false),
+                        ident: Expr::Ident(bound_ident.clone()),
+                    });
+                    bound_ident
+                }
+            };
+            dim_bounds.push(bound_ident);
+        }
 
         // push array arg
         self.input_args.push(Arg {
-            type_: Type::ArrayRef(false),
+            type_: Type::ArrayRef(false, dtype),
             ident: Expr::Ident(arg_ident.clone()),
         });
 
         // push dim args
-        let dim_args = char_indices.iter().map(|c| Arg {
-            type_: Type::Int(false),
-            ident: Expr::Ident(loop_idents[c].0.clone()),
-        });
-        self.input_args.extend(dim_args.clone());
+        self.input_args.extend(dim_args);
 
         Lowered {
             def_block: Block::default(),
@@ -184,11 +347,8 @@ impl Lowerer {
             def_args: Vec::new(),
             loop_idents: loop_idents,
             store_ident: arg_ident,
-            shape: index
-                .chars()
-                .enumerate()
-                .map(|(ind, c)| (self.input_array_counter - 1, ind))
-                .collect(),
+            dim_bounds,
+            dtype,
         }
     }
 
@@ -196,30 +356,104 @@ impl Lowerer {
     fn lower_interior_node(
         &mut self,
         index: &String,
-        op: &char,
+        op: &Op,
+        scan: Option<char>,
         children: &Vec<(Node, String)>,
-        shape: &Vec<(usize, usize)>,
+        child_ids: &[usize],
         schedule: &Schedule,
         pruned_loops: HashSet<(char, usize)>,
-        root: bool,
+        output: Option<String>,
         memo: &mut HashMap<usize, Lowered>,
     ) -> Lowered {
+        let root = output.is_some();
+
+        // merged output dimensions are stored like their indices unmerged, so only the bounds
+        // of the output's dimensions tell them apart
+        let output_dims = index::dims(index);
+        let index = &index::chars(index).into_iter().collect::<String>();
+
         let mut all_char_indices: Vec<char> = children
             .iter()
             .fold(HashSet::new(), |mut all_char_indices, (_child, index)| {
-                all_char_indices.extend(index.chars());
+                all_char_indices.extend(index::chars(index));
                 all_char_indices
             })
             .into_iter()
             .collect();
         all_char_indices.sort();
 
+        // data-dependent ops index one index at positions loaded from an input instead of looping
+        // over it: a gather's input's index found in neither the positions nor the output, and a
+        // scatter's output index found in neither the positions nor the values
+        let indirect = op.position_input().map(|position_ind| {
+            let positions = index::chars(&children[position_ind].1);
+            let candidates: Vec<char> = match op {
+                Op::Gather => index::chars(&children[0].1)
+                    .into_iter()
+                    .filter(|c| !index.contains(*c) && !positions.contains(c))
+                    .collect(),
+                _ => index
+                    .chars()
+                    .filter(|c| {
+                        children[1..]
+                            .iter()
+                            .all(|(_, child_index)| !index::chars(child_index).contains(c))
+                    })
+                    .collect(),
+            };
+            let [c] = candidates[..] else {
+                panic!("Expected one index for the positions of op [{op}] to index, found {candidates:?}.")
+            };
+            c
+        });
+        if let Some(c) = indirect {
+            assert!(
+                *op != Op::Scatter || children[0].1 == *index,
+                "Expected the first input of op [{op}] to be indexed like its output."
+            );
+            assert!(
+                *op != Op::Scatter || pruned_loops.is_empty(),
+                "Expected op [{op}] not to be fused into its consumer."
+            );
+            assert!(
+                schedule.loop_order.iter().all(|(d, _)| *d != c)
+                    && schedule.compute_levels.first().map_or(true, |level| *level == 0),
+                "Expected neither a loop over index '{c}' nor the first input of op [{op}] to be fused."
+            );
+        }
+
+        // a concatenation loops over its output index instead of those of its inputs it joins
+        let concat = match op {
+            Op::Concat => Some(index::concatenated(index, &children[0].1, &children[1].1)),
+            _ => None,
+        };
+        if let Some((c, first, second)) = concat {
+            assert!(
+                schedule
+                    .loop_order
+                    .iter()
+                    .all(|(d, _)| *d != first && *d != second)
+                    && schedule.compute_levels.iter().all(|level| *level == 0),
+                "Expected neither a loop over indices '{first}' and '{second}' nor the inputs of op [{op}] to be fused."
+            );
+            all_char_indices.push(c);
+            all_char_indices.sort();
+        }
+        let joined = |c: char| concat.is_some_and(|(_, first, second)| c == first || c == second);
+
         let mut schedule = schedule.clone(); // Can we avoid this?
         if schedule.loop_order.is_empty() {
             schedule.loop_order = all_char_indices
                 .iter()
+                .filter(|c| indirect != Some(**c) && !joined(**c))
                 .map(|index| (index.clone(), 0))
                 .collect();
+            if self.wide_accumulation {
+                // stable, so output and reduction loops each keep their order
+                schedule
+                    .loop_order
+                    .sort_by_key(|(c, _)| !index.contains(*c));
+            }
         }
         schedule.compute_levels.resize(children.len(), 0);
 
@@ -230,6 +464,9 @@ impl Lowerer {
             .map(|l| *l)
             .collect();
 
+        // the dimension bounds of each child, for indexing its affine dimensions
+        let mut child_dim_bounds: Vec<Vec<String>> = vec![];
+
         // recursively lower children
         // note: the reason this is a fold instead of a map is because the loop_idents are
         //       determined jointly with all siblings. those idents determined by the first child
@@ -239,9 +476,9 @@ impl Lowerer {
             child_alloc_blocks,
             mut child_exec_blocks, // mut so fragments can be pulled out for fusion
             mut child_def_args,
-            loop_idents,
+            mut loop_idents,
             child_store_idents,
-            child_shapes,
+            child_dtypes,
         ): (
             Vec<Block>,
             Vec<Block>,
@@ -249,7 +486,7 @@ impl Lowerer {
             Vec<Arg>,
             HashMap<char, (String, String)>,
             Vec<String>,
-            Vec<Vec<(usize, usize)>>,
+            Vec<DType>,
         ) = children.iter().enumerate().fold(
             (
                 vec![],
@@ -267,14 +504,34 @@ impl Lowerer {
                 mut def_args,
                 mut loop_idents,
                 mut child_store_idents,
-                mut child_shapes,
+                mut child_dtypes,
             ),
              (ind, (child, index))| {
-                // for mapping between child indexing and current node indexing
+                // for mapping between child indexing and current node indexing, through the
+                // dimensions that are plain on both sides
+                let plain_pairs: Vec<(char, char)> = index::dims(&child.index)
+                    .iter()
+                    .zip(index::dims(index).iter())
+                    .filter_map(|(child_dim, dim)| Some((child_dim.plain()?, dim.plain()?)))
+                    .collect();
                 let child_to_current_index: HashMap<char, char> =
-                    child.index.chars().zip(index.chars()).collect();
-                let current_to_child_index: HashMap<char, char> =
-                    index.chars().zip(child.index.chars()).collect();
+                    plain_pairs.iter().copied().collect();
+                let current_to_child_index: HashMap<char, char> = plain_pairs
+                    .iter()
+                    .map(|(child_c, c)| (*c, *child_c))
+                    .collect();
+
+                // an affine operand is read at offsets, and a merged one (or one of a merged
+                // output) across dimensions, so must be computed in full beforehand
+                let plain = index::is_plain(index) && index::is_plain(&child.index);
+                assert!(
+                    plain || schedule.compute_levels[ind] == 0,
+                    "Expected operand [{index}] with affine or merged indices to have compute level 0."
+                );
+                assert!(
+                    !self.outputs.contains_key(&child_ids[ind]) || schedule.compute_levels[ind] == 0,
+                    "Expected operand [{index}] that is also an output to have compute level 0."
+                );
 
                 let pruned_loops: HashSet<(char, usize)> = schedule.loop_order
                     [..schedule.compute_levels[ind]]
@@ -289,14 +546,17 @@ impl Lowerer {
                     def_args: child_def_args,
                     loop_idents: child_loop_idents,
                     store_ident: child_store_ident,
-                    shape: child_shape,
-                } = self.lower_node(&child, pruned_loops, false, memo);
+                    dim_bounds: child_dim_bound_idents,
+                    dtype: child_dtype,
+                } = self.lower_node(&child, child_ids[ind], pruned_loops, memo);
 
                 let child_loop_idents: HashMap<char, (String, String)> = child_loop_idents
                     .into_iter()
+                    .filter(|(c, _)| plain || child_to_current_index.contains_key(c))
                     .map(|(c, x)| (*child_to_current_index.get(&c).unwrap_or(&c), x))
                     //.filter(|(c, _)| !loop_idents.contains_key(c))
                     .collect();
+                child_dim_bounds.push(child_dim_bound_idents);
 
                 def_blocks.push(child_def_block);
                 alloc_blocks.push(child_alloc_block);
@@ -304,7 +564,7 @@ impl Lowerer {
                 Self::merge_args(&mut def_args, child_def_args);
                 loop_idents.extend(child_loop_idents);
                 child_store_idents.push(child_store_ident);
-                child_shapes.push(child_shape);
+                child_dtypes.push(child_dtype);
 
                 (
                     def_blocks,
@@ -313,19 +573,161 @@ impl Lowerer {
                     def_args,
                     loop_idents,
                     child_store_idents,
-                    child_shapes,
+                    child_dtypes,
                 )
             },
         );
 
-        let shape = shape
+        // a plain dimension of an operand merged in its child's output loops over its bound
+        for ((_, index), dim_bounds) in children.iter().zip(child_dim_bounds.iter()) {
+            for (dim, bound_ident) in index::dims(index).iter().zip(dim_bounds.iter()) {
+                if let Some(c) = dim.plain().filter(|c| !loop_idents.contains_key(c)) {
+                    let iterator_ident = format!("i{}", self.base_loop_counter);
+                    self.base_loop_counter += 1;
+                    loop_idents.insert(c, (bound_ident.clone(), iterator_ident));
+                }
+            }
+        }
+
+        // solve for the bounds of indices only found in affine dimensions, e.g. `k` of `(i+k)`
+        // from that dimension's bound and `i`'s, or just the former for an operand with a
+        // boundary mode, or its window for a slice or padding, and for those of merged
+        // dimensions, as given or from the dimension's bound and the others', and for those only
+        // found broadcastable, the largest of their dimensions' bounds
+        let mut bounds: HashMap<char, Expr> = loop_idents
+            .iter()
+            .map(|(c, (bound_ident, _))| (*c, Expr::Ident(bound_ident.clone())))
+            .collect();
+        let (affine_dims, padded): (Vec<(Expr, Dim)>, Vec<bool>) = children
+            .iter()
+            .zip(child_dim_bounds.iter())
+            .flat_map(|((_, index), dim_bounds)| {
+                let padded = index::boundary(index).is_some();
+                index::dims(index)
+                    .into_iter()
+                    .zip(dim_bounds.iter())
+                    .filter(|(dim, _)| dim.plain().is_none())
+                    .map(|(dim, bound_ident)| ((Expr::Ident(bound_ident.clone()), dim), padded))
+                    .collect::<Vec<_>>()
+            })
+            .unzip();
+        let mut solved_bound_statements = vec![];
+        index::solve(&mut bounds, &affine_dims, |ind, c, stride, terms| {
+            let bound_ident = format!("b{}", self.base_loop_counter);
+            let iterator_ident = format!("i{}", self.base_loop_counter);
+            self.base_loop_counter += 1;
+            let (dim_bound, dim) = &affine_dims[ind];
+            let window = dim.window.map(|window| window.extent(dim_bound.clone()));
+            solved_bound_statements.push(Statement::Declaration {
+                ident: bound_ident.clone(),
+                value: match (&dim.merge, window) {
+                    _ if dim.broadcast => Self::create_max_extent(
+                        affine_dims
+                            .iter()
+                            .filter(|(_, dim)| dim.broadcast && dim.terms[0].1 == c)
+                            .map(|(bound, _)| bound.clone())
+                            .collect(),
+                    ),
+                    (Some(merge), _) => {
+                        match merge[dim.terms.iter().position(|(_, d)| *d == c).unwrap()] {
+                            Some(extent) => Expr::Int(extent),
+                            None => Expr::Op {
+                                op: Op::Div,
+                                inputs: vec![
+                                    dim_bound.clone(),
+                                    Self::create_product_extent(
+                                        terms.into_iter().map(|(_, bound)| bound).collect(),
+                                    ),
+                                ],
+                            },
+                        }
+                    }
+                    (None, Some((extents, offset))) => Self::create_sum_extent(extents, offset),
+                    (None, None) => Self::create_solved_extent(
+                        dim_bound.clone(),
+                        stride,
+                        match padded[ind] {
+                            true => vec![],
+                            false => terms,
+                        },
+                    ),
+                },
+                type_: Type::Int(false),
+            });
+            loop_idents.insert(c, (bound_ident.clone(), iterator_ident));
+            Expr::Ident(bound_ident)
+        });
+        if let Some((c, first, second)) = concat {
+            let bound_ident = format!("b{}", self.base_loop_counter);
+            let iterator_ident = format!("i{}", self.base_loop_counter);
+            self.base_loop_counter += 1;
+            solved_bound_statements.push(Statement::Declaration {
+                ident: bound_ident.clone(),
+                value: Self::create_sum_extent(
+                    vec![bounds[&first].clone(), bounds[&second].clone()],
+                    0,
+                ),
+                type_: Type::Int(false),
+            });
+            loop_idents.insert(c, (bound_ident, iterator_ident));
+        }
+        if let Some(c) = all_char_indices
+            .iter()
+            .find(|c| !loop_idents.contains_key(c))
+        {
+            panic!("Could not infer the extent of index '{c}'.");
+        }
+
+        // a merged output dimension is bound by the product of its indices'
+        let output_dim_bounds: Vec<String> = output_dims
             .iter()
-            .map(|(child_ind, dim)| child_shapes[*child_ind][*dim])
-            .collect::<Vec<_>>();
+            .map(|dim| match dim.plain() {
+                Some(c) => loop_idents[&c].0.clone(),
+                None => {
+                    let bound_ident = format!("b{}", self.base_loop_counter);
+                    self.base_loop_counter += 1;
+                    solved_bound_statements.push(Statement::Declaration {
+                        ident: bound_ident.clone(),
+                        value: Self::create_product_extent(
+                            dim.terms
+                                .iter()
+                                .map(|(_, c)| Expr::Ident(loop_idents[c].0.clone()))
+                                .collect(),
+                        ),
+                        type_: Type::Int(false),
+                    });
+                    bound_ident
+                }
+            })
+            .collect();
+
+        // a select's mask and a data-dependent op's positions don't take part in its value
+        let value_dtypes: Vec<DType> = match op {
+            Op::Select => child_dtypes[1..].to_vec(),
+            _ => child_dtypes
+                .iter()
+                .enumerate()
+                .filter(|(ind, _)| op.position_input() != Some(*ind))
+                .map(|(_, child_dtype)| *child_dtype)
+                .collect(),
+        };
+        let dtype = match op {
+            _ if op.is_comparison() => DType::Bool,
+            Op::ArgMax | Op::ArgMin => DType::I32,
+            _ => value_dtypes[1..]
+                .iter()
+                .fold(value_dtypes[0], |dtype, child_dtype| {
+                    dtype.promote(*child_dtype)
+                }),
+        };
+        assert!(
+            *op != Op::Scatter || dtype != DType::Bool,
+            "Expected numeric inputs to op [{op}]."
+        );
 
-        let store_ident = match root {
-            true => "out".to_string(),
-            false => {
+        let store_ident = match output {
+            Some(ident) => ident,
+            None => {
                 let ident = format!("s{}", self.store_counter);
                 self.store_counter += 1;
                 ident
@@ -371,38 +773,162 @@ impl Lowerer {
             })
             .collect();
 
+        // accumulating stores start from the identity of their reduction
+        let reducing = children.iter().any(|(_, child_index)| {
+            index::chars(child_index)
+                .iter()
+                .any(|c| !index.contains(*c))
+        });
+        let identity = Self::accumulation_identity(op, children.len(), reducing);
+        if let Some(c) = scan {
+            assert!(
+                !reducing && index.contains(c),
+                "Expected scanned index '{c}' to be the only one accumulated along."
+            );
+            assert!(
+                Self::accumulation_identity(op, children.len(), true).is_some(),
+                "Expected an accumulating op to scan with, got [{op}]."
+            );
+            let ranks: Vec<usize> = schedule
+                .loop_order
+                .iter()
+                .filter(|(d, _)| *d == c)
+                .map(|(_, rank)| *rank)
+                .collect();
+            assert!(
+                ranks.is_sorted(),
+                "Expected the split loops of scanned index '{c}' to be ordered outermost first."
+            );
+        }
+
+        // arg reductions pair their index store with a store of the extreme values so far,
+        // starting from the least or greatest element of their type
+        let arg_values = match op {
+            Op::ArgMax | Op::ArgMin => {
+                assert!(reducing, "Expected a reduced index for op [{op}].");
+                let (least, greatest) = child_dtypes[0].bounds();
+                let initial_value = match op {
+                    Op::ArgMax => least,
+                    _ => greatest,
+                };
+                Some((format!("{store_ident}_v"), child_dtypes[0], initial_value))
+            }
+            _ => None,
+        };
+        let store_identity = match arg_values {
+            Some(_) => Some(0.),
+            None => identity,
+        };
+
         let alloc_statement = Statement::Declaration {
             ident: store_ident.clone(),
             value: Expr::Alloc {
-                initial_value: 0.,
+                initial_value: store_identity.unwrap_or(0.),
+                dtype,
                 shape: index.chars().map(|c| loop_idents[&c].0.clone()).collect(),
             },
-            type_: Type::Array(true),
+            type_: Type::Array(true, dtype),
+        };
+        let arg_values_alloc_statement = arg_values.iter().map(
+            |(ident, values_dtype, initial_value)| Statement::Declaration {
+                ident: ident.clone(),
+                value: Expr::Alloc {
+                    initial_value: *initial_value,
+                    dtype: *values_dtype,
+                    shape: index.chars().map(|c| loop_idents[&c].0.clone()).collect(),
+                },
+                type_: Type::Array(true, *values_dtype),
+            },
+        );
+
+        // with wide accumulation, reductions accumulate in a scalar across the innermost
+        // (contiguous) reduction loops
+        let n_reduction_loops = schedule
+            .loop_order
+            .iter()
+            .rev()
+            .take_while(|(c, _)| !index.contains(*c))
+            .count();
+        let accumulator = match identity {
+            Some(_) if self.wide_accumulation && n_reduction_loops > 0 => {
+                Some((format!("{store_ident}_acc"), dtype.accumulator()))
+            }
+            _ => None,
         };
 
         // TODO: The mapping should probably be done in the present function instead of passing
         //       the hashmap here.
         // TODO: stop splitting ident map
-        let op_statement = Self::create_op_statement(
-            op,
-            // bound_idents
-            &loop_idents
-                .iter()
-                .map(|(c, (ident, _))| (*c, ident.clone()))
-                .collect(),
-            // base_iterator_idents
-            &loop_idents
-                .iter()
-                .map(|(c, (_, ident))| (*c, ident.clone()))
-                .collect(),
-            &child_store_idents,
-            &children
-                .iter()
-                .map(|(child, index)| index.clone())
-                .collect(),
-            &store_ident,
-            &index,
-        );
+        let operands: Vec<(&String, &String, &[String], DType)> = child_store_idents
+            .iter()
+            .zip(children.iter())
+            .zip(child_dim_bounds.iter())
+            .zip(child_dtypes.iter())
+            .map(|(((ident, (_, index)), dim_bounds), child_dtype)| {
+                (ident, index, &dim_bounds[..], *child_dtype)
+            })
+            .collect();
+        let op_statement = match (&arg_values, indirect, concat) {
+            (_, Some(c), _) => Self::create_indirect_statement(
+                op,
+                c,
+                &loop_idents,
+                &operands,
+                &store_ident,
+                index,
+                dtype,
+            ),
+            (_, _, Some(concatenated)) => Self::create_concat_statement(
+                concatenated,
+                &loop_idents,
+                &operands,
+                &store_ident,
+                index,
+                dtype,
+            ),
+            (Some((values_ident, values_dtype, _)), None, None) => Self::create_arg_statement(
+                op,
+                &loop_idents,
+                (
+                    &child_store_idents[0],
+                    &children[0].1,
+                    &child_dim_bounds[0],
+                    *values_dtype,
+                ),
+                values_ident,
+                &store_ident,
+                index,
+            ),
+            (None, None, None) => Self::create_op_statement(
+                op,
+                // bound_idents
+                &loop_idents
+                    .iter()
+                    .map(|(c, (ident, _))| (*c, ident.clone()))
+                    .collect(),
+                // base_iterator_idents
+                &loop_idents
+                    .iter()
+                    .map(|(c, (_, ident))| (*c, ident.clone()))
+                    .collect(),
+                &child_store_idents,
+                &children.iter().map(|(_, index)| index.clone()).collect(),
+                &child_dim_bounds,
+                &store_ident,
+                &index,
+                &child_dtypes,
+                dtype,
+                accumulator.as_ref(),
+                scan,
+            ),
+        };
+
+        // output loops run in parallel, except along a scan, and a scatter's adds are atomic so
+        // all of its loops can
+        let parallel_char_indices: HashSet<char> = match op {
+            Op::Scatter => all_char_indices.iter().copied().collect(),
+            _ => index.chars().filter(|c| scan != Some(*c)).collect(),
+        };
 
         // TODO: stop splitting ident map
         let mut loop_statements: Vec<Statement> = Self::create_empty_loop_statements(
@@ -416,7 +942,7 @@ impl Lowerer {
                 .map(|(c, (ident, _))| (*c, ident.clone()))
                 .collect(),
             &split_factor_idents,
-            &index,
+            &parallel_char_indices,
         );
 
         // partition fragments from blocks
@@ -453,34 +979,81 @@ impl Lowerer {
                 .extend(child_exec_fragment.statements.clone());
         }
 
-        let loop_stack: Statement =
-            loop_statements
-                .into_iter()
-                .fold(op_statement, |loop_stack, mut loop_| {
-                    if let Statement::Loop { ref mut body, .. } = loop_ {
-                        body.statements.push(loop_stack);
-                    }
-                    loop_
-                });
+        // load the accumulator before the reduction loops and store it back after
+        let accumulate = |statements: Vec<Statement>| -> Vec<Statement> {
+            let Some((acc_ident, acc_dtype)) = &accumulator else {
+                return statements;
+            };
+            let out_expr = Expr::Indexed {
+                ident: store_ident.clone(),
+                index: Box::new(Self::create_affine_index(
+                    index
+                        .chars()
+                        .map(|c| Expr::Ident(loop_idents[&c].1.clone()))
+                        .collect(),
+                    index.chars().map(|c| loop_idents[&c].0.clone()).collect(),
+                )),
+            };
+            [
+                vec![Statement::Declaration {
+                    ident: acc_ident.clone(),
+                    value: Self::cast(out_expr.clone(), dtype, *acc_dtype),
+                    type_: Type::Scalar(true, *acc_dtype),
+                }],
+                statements,
+                vec![Statement::Assignment {
+                    left: out_expr,
+                    right: Self::cast(Expr::Ident(acc_ident.clone()), *acc_dtype, dtype),
+                }],
+            ]
+            .concat()
+        };
+
+        let mut loop_stack = vec![op_statement];
+        for (depth, mut loop_) in loop_statements.into_iter().enumerate() {
+            if depth == n_reduction_loops {
+                loop_stack = accumulate(loop_stack);
+            }
+            if let Statement::Loop { ref mut body, .. } = loop_ {
+                body.statements.extend(loop_stack);
+            }
+            loop_stack = vec![loop_];
+        }
+        if n_reduction_loops == n_loop_statements {
+            loop_stack = accumulate(loop_stack);
+        }
 
         if root {
             // push array arg
-            self.input_args.push(Arg {
-                type_: Type::ArrayRef(true),
+            self.output_args.push(Arg {
+                type_: Type::ArrayRef(true, dtype),
                 ident: Expr::Ident(store_ident.clone()),
             });
 
             // push dim args
-            let dim_args = (0..Self::get_char_indices(&index).len()).map(|ind| Arg {
+            let dim_args = (0..output_dim_bounds.len()).map(|ind| Arg {
                 type_: Type::Int(false),
                 ident: Expr::Ident(format!("{}_{ind}", store_ident.clone())),
             });
-            self.input_args.extend(dim_args.clone());
+            self.output_args.extend(dim_args.clone());
+
+            // followed by an arg reduction's extreme values
+            if let Some((ident, values_dtype, _)) = &arg_values {
+                self.output_args.push(Arg {
+                    type_: Type::ArrayRef(true, *values_dtype),
+                    ident: Expr::Ident(ident.clone()),
+                });
+                self.output_args
+                    .extend((0..output_dim_bounds.len()).map(|ind| Arg {
+                        type_: Type::Int(false),
+                        ident: Expr::Ident(format!("{ident}_{ind}")),
+                    }));
+            }
         };
 
         let function_ident = format!("_{}", store_ident.clone());
 
-        let exec_statements = [split_factor_assignment_statements, vec![loop_stack]].concat();
+        let exec_statements = [split_factor_assignment_statements, loop_stack].concat();
 
         let alloc_block = Block {
             statements: [
@@ -488,24 +1061,40 @@ impl Lowerer {
                     .into_iter()
                     .flat_map(|block| block.statements)
                     .collect(),
+                solved_bound_statements,
                 if root { vec![] } else { vec![alloc_statement] }, // TODO: Make not hacky.
+                if root {
+                    vec![]
+                } else {
+                    arg_values_alloc_statement.collect()
+                },
             ]
             .concat(),
         };
 
-        // this will get drained for full kernels and returned populated for fragments
-        let mut def_args: Vec<Arg> = [
+        // this will get drained for full kernels and returned populated for fragments. a store
+        // read by several operands is passed once.
+        let mut def_args: Vec<Arg> = vec![];
+        let node_args: Vec<Arg> = [
             child_store_idents
                 .iter()
-                .map(|ident| Arg {
-                    type_: Type::ArrayRef(false),
+                .zip(child_dtypes.iter())
+                .map(|(ident, child_dtype)| Arg {
+                    type_: Type::ArrayRef(false, *child_dtype),
                     ident: Expr::Ident(ident.clone()),
                 })
                 .collect::<Vec<_>>(),
             vec![Arg {
-                type_: Type::ArrayRef(true),
+                type_: Type::ArrayRef(true, dtype),
                 ident: Expr::Ident(store_ident.clone()),
             }],
+            arg_values
+                .iter()
+                .map(|(ident, values_dtype, _)| Arg {
+                    type_: Type::ArrayRef(true, *values_dtype),
+                    ident: Expr::Ident(ident.clone()),
+                })
+                .collect(),
             all_char_indices
                 .iter()
                 .map(|c| Arg {
@@ -515,126 +1104,628 @@ impl Lowerer {
                 .collect::<Vec<_>>(),
         ]
         .concat();
+        Self::merge_args(&mut def_args, node_args);
 
+        // affine dimensions are indexed with the bounds of the children's dimensions
+        let affine_dim_args = affine_dims
+            .iter()
+            .map(|(bound, _)| Arg {
+                type_: Type::Int(false),
+                ident: bound.clone(),
+            })
+            .collect();
+        Self::merge_args(&mut def_args, affine_dim_args);
         Self::merge_args(&mut def_args, child_def_args);
 
         // this will get drained for full kernels and returned populated for fragments
         let call_args: Vec<Arg> = def_args
             .iter()
             .map(|arg| match (arg.type_.clone(), arg.ident.clone()) {
-                (Type::ArrayRef(mutable), Expr::Ident(s)) => Arg {
-                    type_: Type::ArrayRef(mutable),
+                (Type::ArrayRef(mutable, dtype), Expr::Ident(s)) => Arg {
+                    type_: Type::ArrayRef(mutable, dtype),
                     ident: Expr::Ref(s, mutable),
                 },
                 (Type::Int(mutable), Expr::Ident(s)) => Arg {
-                    type_: Type::ArrayRef(mutable),
+                    type_: Type::Int(mutable),
                     ident: Expr::Ident(s),
                 },
                 _ => panic!("Invalid argument."),
             })
             .collect();
 
-        let (def_block, exec_block) = if pruned_loops.is_empty() {
-            let def_block = Block {
-                statements: [
-                    child_def_blocks
-                        .into_iter()
-                        .flat_map(|block| block.statements)
-                        .collect(),
-                    vec![Statement::Function {
-                        ident: function_ident.clone(),
-                        args: def_args.drain(..).collect(),
-                        body: Block {
-                            statements: exec_statements,
-                        },
-                    }],
-                ]
-                .concat(),
-            };
+        let (def_block, exec_block) =
+            if pruned_loops.is_empty() {
+                // the caller owns the root store, so an accumulating root is initialized in place
+                // and a scatter adds to a copy of its first input
+                let init = match (store_identity, op) {
+                    (_, Op::Scatter) => Some((
+                        Self::cast(
+                            Expr::Indexed {
+                                ident: child_store_idents[0].clone(),
+                                index: Box::new(Expr::Ident("i".to_string())),
+                            },
+                            child_dtypes[0],
+                            dtype,
+                        ),
+                        vec![Arg {
+                            type_: Type::ArrayRef(false, child_dtypes[0]),
+                            ident: Expr::Ident(child_store_idents[0].clone()),
+                        }],
+                    )),
+                    (Some(identity), _) if root => Some((Expr::Literal(identity, dtype), vec![])),
+                    _ => None,
+                };
+                // as are a root arg reduction's extreme values
+                let values_init = arg_values.iter().filter(|_| root).map(
+                    |(ident, values_dtype, initial_value)| {
+                        let value = Expr::Literal(*initial_value, *values_dtype);
+                        (ident.clone(), *values_dtype, value, vec![])
+                    },
+                );
+                let (init_def, init_call): (Vec<Statement>, Vec<Statement>) = init
+                    .map(|(value, source_args)| (store_ident.clone(), dtype, value, source_args))
+                    .into_iter()
+                    .chain(values_init)
+                    .map(|(ident, dtype, value, source_args)| {
+                        Self::create_init_function(
+                            &ident,
+                            dtype,
+                            value,
+                            source_args,
+                            &Self::get_char_indices(index)
+                                .iter()
+                                .map(|c| loop_idents[c].0.clone())
+                                .collect::<Vec<_>>(),
+                            &index
+                                .chars()
+                                .map(|c| loop_idents[&c].0.clone())
+                                .collect::<Vec<_>>(),
+                        )
+                    })
+                    .unzip();
 
-            let call = Statement::Call {
-                ident: function_ident.clone(),
-                args: call_args,
-            };
+                let def_block = Block {
+                    statements: [
+                        child_def_blocks
+                            .into_iter()
+                            .flat_map(|block| block.statements)
+                            .collect(),
+                        vec![Statement::Function {
+                            ident: function_ident.clone(),
+                            args: def_args.drain(..).collect(),
+                            body: Block {
+                                statements: exec_statements,
+                            },
+                        }],
+                        init_def,
+                    ]
+                    .concat(),
+                };
 
-            let exec_block = Block {
-                statements: [
-                    child_exec_blocks
-                        .into_iter()
-                        .flat_map(|block| block.statements)
-                        .collect(),
-                    vec![call],
-                ]
-                .concat(),
-            };
+                let call = Statement::Call {
+                    ident: function_ident.clone(),
+                    args: call_args,
+                };
 
-            (def_block, exec_block)
-        } else {
-            let exec_block = Block {
-                statements: [
-                    child_exec_blocks
-                        .into_iter()
-                        .flat_map(|block| block.statements)
-                        .collect(),
-                    exec_statements,
-                ]
-                .concat(),
+                let exec_block = Block {
+                    statements: [
+                        child_exec_blocks
+                            .into_iter()
+                            .flat_map(|block| block.statements)
+                            .collect(),
+                        init_call,
+                        vec![call],
+                    ]
+                    .concat(),
+                };
+
+                (def_block, exec_block)
+            } else {
+                let exec_block = Block {
+                    statements: [
+                        child_exec_blocks
+                            .into_iter()
+                            .flat_map(|block| block.statements)
+                            .collect(),
+                        exec_statements,
+                    ]
+                    .concat(),
+                };
+                (Block::default(), exec_block)
             };
-            (Block::default(), exec_block)
-        };
 
         Lowered {
             def_block,
             alloc_block,
             exec_block,
             def_args,
+            dim_bounds: output_dim_bounds,
             loop_idents,
             store_ident,
-            shape,
+            dtype,
         }
     }
 
-    fn create_op_statement(
-        op: &char,
-        bound_idents: &HashMap<char, String>,
-        base_iterator_idents: &HashMap<char, String>,
-        child_store_idents: &Vec<String>,
-        child_indices: &Vec<String>,
+    /// The identity a node of `op` over `n_inputs` inputs starts its store from, if it accumulates
+    /// into it
+    fn accumulation_identity(op: &Op, n_inputs: usize, reducing: bool) -> Option<f64> {
+        match (op, n_inputs) {
+            (Op::Add, 1) => Some(0.),
+            (Op::Mul, 1) => Some(1.),
+            // max and min reductions; otherwise relu and its mirror
+            (Op::Max, 1) if reducing => Some(f64::NEG_INFINITY),
+            (Op::Min, 1) if reducing => Some(f64::INFINITY),
+            _ => None,
+        }
+    }
+
+    /// Create a function filling the `dtype` array `store_ident` of shape `shape` with `value`,
+    /// which may read the `source_args` arrays at the flat position `i`, and a call to it
+    fn create_init_function(
+        store_ident: &String,
+        dtype: DType,
+        value: Expr,
+        source_args: Vec<Arg>,
+        bound_idents: &[String],
+        shape: &[String],
+    ) -> (Statement, Statement) {
+        let ident = format!("_{store_ident}_init");
+        let args = [
+            source_args,
+            vec![Arg {
+                type_: Type::ArrayRef(true, dtype),
+                ident: Expr::Ident(store_ident.clone()),
+            }],
+            bound_idents
+                .iter()
+                .map(|bound_ident| Arg {
+                    type_: Type::Int(false),
+                    ident: Expr::Ident(bound_ident.clone()),
+                })
+                .collect(),
+        ]
+        .concat();
+        let len = match shape.len() {
+            0 => Expr::Int(1),
+            _ => Expr::Op {
+                op: Op::Mul,
+                inputs: shape.iter().map(|s| Expr::Ident(s.clone())).collect(),
+            },
+        };
+        let def = Statement::Function {
+            ident: ident.clone(),
+            args: args.clone(),
+            body: Block {
+                statements: vec![Statement::Loop {
+                    index: "i".to_string(),
+                    bound: len,
+                    body: Block {
+                        statements: vec![Statement::Assignment {
+                            left: Expr::Indexed {
+                                ident: store_ident.clone(),
+                                index: Box::new(Expr::Ident("i".to_string())),
+                            },
+                            right: value,
+                        }],
+                    },
+                    parallel: true,
+                }],
+            },
+        };
+        let call = Statement::Call {
+            ident,
+            args: args
+                .into_iter()
+                .map(|arg| match (&arg.type_, &arg.ident) {
+                    (Type::ArrayRef(mutable, _), Expr::Ident(ident)) => Arg {
+                        ident: Expr::Ref(ident.clone(), *mutable),
+                        ..arg
+                    },
+                    _ => arg,
+                })
+                .collect(),
+        };
+        (def, call)
+    }
+
+    /// Create the statement of a data-dependent op at the positions loaded from its `operands`'
+    /// second: a gather's read of its first operand, or a scatter's atomic add of its third (or 1).
+    /// Positions outside the bound of `indirect` are skipped, a gather reading 0 for them
+    fn create_indirect_statement(
+        op: &Op,
+        indirect: char,
+        loop_idents: &HashMap<char, (String, String)>,
+        operands: &[(&String, &String, &[String], DType)],
         store_ident: &String,
         index: &String,
+        dtype: DType,
     ) -> Statement {
-        assert_eq!(child_store_idents.len(), child_indices.len());
+        let bound_idents: HashMap<char, String> = loop_idents
+            .iter()
+            .map(|(c, (ident, _))| (*c, ident.clone()))
+            .collect();
+        let mut iterators: HashMap<char, Expr> = loop_idents
+            .iter()
+            .map(|(c, (_, ident))| (*c, Expr::Ident(ident.clone())))
+            .collect();
+        let load =
+            |(ident, index, dim_bounds, operand_dtype): &(&String, &String, &[String], DType),
+             iterators: &HashMap<char, Expr>| {
+                Self::cast(
+                    Self::create_operand_load(
+                        ident,
+                        index,
+                        dim_bounds,
+                        &bound_idents,
+                        iterators,
+                        *operand_dtype,
+                    ),
+                    *operand_dtype,
+                    dtype,
+                )
+            };
 
-        let out_expr = Expr::Indexed {
+        let (position_ident, position_index, position_dim_bounds, position_dtype) = operands[1];
+        let position = Self::create_operand_load(
+            position_ident,
+            position_index,
+            position_dim_bounds,
+            &bound_idents,
+            &iterators,
+            position_dtype,
+        );
+        let position = Self::cast(position, position_dtype, DType::I32);
+        iterators.insert(indirect, Expr::AsIndex(Box::new(position.clone())));
+
+        // in range when nonnegative and below the bound
+        let in_range = [
+            Expr::Op {
+                op: Op::Ge,
+                inputs: vec![position.clone(), Expr::Literal(0., DType::I32)],
+            },
+            Expr::Op {
+                op: Op::Lt,
+                inputs: vec![
+                    Expr::AsIndex(Box::new(position)),
+                    Expr::Ident(bound_idents[&indirect].clone()),
+                ],
+            },
+        ];
+
+        let out = Expr::Indexed {
             ident: store_ident.clone(),
+            index: Box::new(Self::create_affine_index(
+                index.chars().map(|c| iterators[&c].clone()).collect(),
+                index.chars().map(|c| bound_idents[&c].clone()).collect(),
+            )),
+        };
+        match op {
+            Op::Gather => Statement::Assignment {
+                left: out,
+                right: in_range.into_iter().rev().fold(
+                    load(&operands[0], &iterators),
+                    |value, condition| Expr::Op {
+                        op: Op::Select,
+                        inputs: vec![condition, value, Expr::Literal(0., dtype)],
+                    },
+                ),
+            },
+            _ => in_range.into_iter().rev().fold(
+                Statement::AtomicAdd {
+                    left: out,
+                    right: match operands.get(2) {
+                        Some(values) => load(values, &iterators),
+                        None => Expr::Literal(1., dtype),
+                    },
+                    dtype,
+                },
+                |statement, condition| Statement::If {
+                    condition,
+                    body: Block {
+                        statements: vec![statement],
+                    },
+                },
+            ),
+        }
+    }
+
+    /// Create the statement of a concatenation along `c`: below the bound of the first of its
+    /// `operands`' joined index, a read of it there, and otherwise of the second past that
+    fn create_concat_statement(
+        (c, first, second): (char, char, char),
+        loop_idents: &HashMap<char, (String, String)>,
+        operands: &[(&String, &String, &[String], DType)],
+        store_ident: &str,
+        index: &str,
+        dtype: DType,
+    ) -> Statement {
+        let bound_idents: HashMap<char, String> = loop_idents
+            .iter()
+            .map(|(c, (ident, _))| (*c, ident.clone()))
+            .collect();
+        let iterators: HashMap<char, Expr> = loop_idents
+            .iter()
+            .map(|(c, (_, ident))| (*c, Expr::Ident(ident.clone())))
+            .collect();
+        let iterator = iterators[&c].clone();
+        let first_bound = Expr::Ident(bound_idents[&first].clone());
+        let load =
+            |(ident, index, dim_bounds, operand_dtype): &(&String, &String, &[String], DType),
+             joined: char,
+             position: Expr| {
+                let mut iterators = iterators.clone();
+                iterators.insert(joined, position);
+                Self::cast(
+                    Self::create_operand_load(
+                        ident,
+                        index,
+                        dim_bounds,
+                        &bound_idents,
+                        &iterators,
+                        *operand_dtype,
+                    ),
+                    *operand_dtype,
+                    dtype,
+                )
+            };
+
+        // only the branch taken is evaluated, so the second's position never underflows
+        Statement::Assignment {
+            left: Expr::Indexed {
+                ident: store_ident.to_string(),
+                index: Box::new(Self::create_affine_index(
+                    index.chars().map(|c| iterators[&c].clone()).collect(),
+                    index.chars().map(|c| bound_idents[&c].clone()).collect(),
+                )),
+            },
+            right: Expr::Op {
+                op: Op::Select,
+                inputs: vec![
+                    Expr::Op {
+                        op: Op::Lt,
+                        inputs: vec![iterator.clone(), first_bound.clone()],
+                    },
+                    load(&operands[0], first, iterator.clone()),
+                    load(
+                        &operands[1],
+                        second,
+                        Expr::Op {
+                            op: Op::Sub,
+                            inputs: vec![iterator, first_bound],
+                        },
+                    ),
+                ],
+            },
+        }
+    }
+
+    /// Create the compare-and-update statement of an arg reduction: where the `child` element
+    /// beats the extreme so far in `values_ident`, record it and its reduced position
+    fn create_arg_statement(
+        op: &Op,
+        loop_idents: &HashMap<char, (String, String)>,
+        (child_store_ident, child_index, child_dim_bounds, child_dtype): (
+            &String,
+            &String,
+            &[String],
+            DType,
+        ),
+        values_ident: &String,
+        store_ident: &String,
+        index: &String,
+    ) -> Statement {
+        let indexed = |ident: &String, index: &String| Expr::Indexed {
+            ident: ident.clone(),
             index: Box::new(Self::create_affine_index(
                 index
                     .chars()
-                    .map(|c| base_iterator_idents[&c].clone())
+                    .map(|c| Expr::Ident(loop_idents[&c].1.clone()))
                     .collect(),
-                index.chars().map(|c| bound_idents[&c].clone()).collect(),
+                index.chars().map(|c| loop_idents[&c].0.clone()).collect(),
             )),
         };
+        let value = Self::create_operand_load(
+            child_store_ident,
+            child_index,
+            child_dim_bounds,
+            &loop_idents
+                .iter()
+                .map(|(c, (ident, _))| (*c, ident.clone()))
+                .collect(),
+            &loop_idents
+                .iter()
+                .map(|(c, (_, ident))| (*c, Expr::Ident(ident.clone())))
+                .collect(),
+            child_dtype,
+        );
+        let best = indexed(values_ident, index);
+
+        let reduced_index: String = index::chars(child_index)
+            .into_iter()
+            .filter(|c| !index.contains(*c))
+            .collect();
+        let position = Self::create_affine_index(
+            reduced_index
+                .chars()
+                .map(|c| Expr::Ident(loop_idents[&c].1.clone()))
+                .collect(),
+            reduced_index
+                .chars()
+                .map(|c| loop_idents[&c].0.clone())
+                .collect(),
+        );
+
+        let compute = child_dtype.compute();
+        Statement::If {
+            condition: Expr::Op {
+                op: match op {
+                    Op::ArgMax => Op::Gt,
+                    _ => Op::Lt,
+                },
+                inputs: vec![
+                    Self::cast(value.clone(), child_dtype, compute),
+                    Self::cast(best.clone(), child_dtype, compute),
+                ],
+            },
+            body: Block {
+                statements: vec![
+                    Statement::Assignment {
+                        left: best,
+                        right: value,
+                    },
+                    Statement::Assignment {
+                        left: indexed(store_ident, index),
+                        right: Expr::Cast {
+                            value: Box::new(position),
+                            from: DType::I32,
+                            to: DType::I32,
+                        },
+                    },
+                ],
+            },
+        }
+    }
+
+    /// Wrap `expr` in a conversion from `from` to `to`, if they differ
+    fn cast(expr: Expr, from: DType, to: DType) -> Expr {
+        match from == to {
+            true => expr,
+            false => Expr::Cast {
+                value: Box::new(expr),
+                from,
+                to,
+            },
+        }
+    }
+
+    fn create_op_statement(
+        op: &Op,
+        bound_idents: &HashMap<char, String>,
+        base_iterator_idents: &HashMap<char, String>,
+        child_store_idents: &Vec<String>,
+        child_indices: &Vec<String>,
+        child_dim_bounds: &[Vec<String>],
+        store_ident: &String,
+        index: &String,
+        child_dtypes: &[DType],
+        dtype: DType,
+        accumulator: Option<&(String, DType)>,
+        scan: Option<char>,
+    ) -> Statement {
+        assert_eq!(child_store_idents.len(), child_indices.len());
+
+        // a scan accumulates along its index like a reduction
+        let reducing = scan.is_some()
+            || child_indices.iter().any(|child_index| {
+                index::chars(child_index)
+                    .iter()
+                    .any(|c| !index.contains(*c))
+            });
+        let identity = Self::accumulation_identity(op, child_indices.len(), reducing);
+        let accumulating = identity.is_some();
+
+        // an accumulator stands in for the store
+        let (out_expr, dtype) = match accumulator {
+            Some((acc_ident, acc_dtype)) => (Expr::Ident(acc_ident.clone()), *acc_dtype),
+            None => (
+                Expr::Indexed {
+                    ident: store_ident.clone(),
+                    index: Box::new(Self::create_affine_index(
+                        index
+                            .chars()
+                            .map(|c| Expr::Ident(base_iterator_idents[&c].clone()))
+                            .collect(),
+                        index.chars().map(|c| bound_idents[&c].clone()).collect(),
+                    )),
+                },
+                dtype,
+            ),
+        };
+
+        // elements are loaded into, combined in, and stored from a common compute type, except a
+        // select's mask which is loaded as a bool
+        let masked = *op == Op::Select;
+        let values = match masked {
+            true => &child_dtypes[1..],
+            false => child_dtypes,
+        };
+        let compute = values
+            .iter()
+            .fold(dtype, |compute, child_dtype| compute.promote(*child_dtype))
+            .compute();
 
         let mut in_exprs: Vec<Expr> = child_store_idents
             .iter()
             .zip(child_indices.iter())
-            .map(|(ident, index)| Expr::Indexed {
-                ident: ident.clone(),
-                index: Box::new(Self::create_affine_index(
-                    index
-                        .chars()
-                        .map(|c| base_iterator_idents[&c].clone())
+            .zip(child_dim_bounds.iter())
+            .zip(child_dtypes.iter())
+            .enumerate()
+            .map(|(i, (((ident, index), dim_bounds), child_dtype))| {
+                let load = Self::create_operand_load(
+                    ident,
+                    index,
+                    dim_bounds,
+                    bound_idents,
+                    &base_iterator_idents
+                        .iter()
+                        .map(|(c, ident)| (*c, Expr::Ident(ident.clone())))
                         .collect(),
-                    index.chars().map(|c| bound_idents[&c].clone()).collect(),
-                )),
+                    *child_dtype,
+                );
+                match masked && i == 0 {
+                    true => Self::cast(load, *child_dtype, DType::Bool),
+                    false => Self::cast(load, *child_dtype, compute),
+                }
             })
             .collect();
 
-        if in_exprs.len() == 1 && matches!(op, '+' | '*') {
+        if accumulating {
+            let carried = match (scan, identity) {
+                // a scan carries the previous element along its index, or starts from identity
+                (Some(c), Some(identity)) => {
+                    let stride = index
+                        .chars()
+                        .skip_while(|d| *d != c)
+                        .skip(1)
+                        .map(|d| Expr::Ident(bound_idents[&d].clone()))
+                        .collect::<Vec<_>>();
+                    let stride = match stride.len() {
+                        0 => Expr::Int(1),
+                        _ => Expr::Op {
+                            op: Op::Mul,
+                            inputs: stride,
+                        },
+                    };
+                    let Expr::Indexed { ident, index } = out_expr.clone() else {
+                        unreachable!()
+                    };
+                    let previous = Expr::Indexed {
+                        ident,
+                        index: Box::new(Expr::Op {
+                            op: Op::Sub,
+                            inputs: vec![*index, stride],
+                        }),
+                    };
+                    Expr::Op {
+                        op: Op::Select,
+                        inputs: vec![
+                            Expr::Op {
+                                op: Op::Gt,
+                                inputs: vec![
+                                    Expr::Ident(base_iterator_idents[&c].clone()),
+                                    Expr::Int(0),
+                                ],
+                            },
+                            Self::cast(previous, dtype, compute),
+                            Expr::Literal(identity, compute),
+                        ],
+                    }
+                }
+                _ => Self::cast(out_expr.clone(), dtype, compute),
+            };
             // Pushing to front here shouldn't be a problem unless we start allowing ops of
             // arbitrary inputs.
-            in_exprs.insert(0, out_expr.clone());
+            in_exprs.insert(0, carried);
             assert_eq!(
                 in_exprs.len(),
                 2,
@@ -642,12 +1733,28 @@ impl Lowerer {
             );
         }
 
+        // a fill only takes the extents of its inputs
+        if let Some(value) = op.fill() {
+            return Statement::Assignment {
+                left: out_expr,
+                right: Expr::Literal(value, dtype),
+            };
+        }
+
+        let result = match op.is_comparison() {
+            true => DType::Bool,
+            false => compute,
+        };
         Statement::Assignment {
             left: out_expr,
-            right: Expr::Op {
-                op: *op,
-                inputs: in_exprs,
-            },
+            right: Self::cast(
+                Expr::Op {
+                    op: *op,
+                    inputs: in_exprs,
+                },
+                result,
+                dtype,
+            ),
         }
     }
 
@@ -656,7 +1763,7 @@ impl Lowerer {
         base_iterator_idents: &HashMap<char, String>,
         bound_idents: &HashMap<char, String>,
         split_factor_idents: &HashMap<char, Vec<String>>,
-        index: &String,
+        parallel_char_indices: &HashSet<char>,
     ) -> Vec<Statement> {
         let mut statements = vec![];
 
@@ -667,7 +1774,6 @@ impl Lowerer {
             .map(|(c, _splits_factors)| *c)
             .collect();
 
-        let output_char_indices: HashSet<char> = index.chars().collect();
         for (char_index, rank) in schedule.loop_order.iter().rev() {
             let splits = schedule.splits.get(char_index);
 
@@ -706,7 +1812,7 @@ impl Lowerer {
                         vec![]
                     },
                 },
-                parallel: output_char_indices.contains(&char_index),
+                parallel: parallel_char_indices.contains(char_index),
             });
         }
 
@@ -718,7 +1824,7 @@ impl Lowerer {
         split_factors_idents: &Vec<String>,
     ) -> Expr {
         let tile_width_expr = Expr::Op {
-            op: '*',
+            op: Op::Mul,
             inputs: split_factors_idents
                 .iter()
                 .map(|ident| Expr::Ident(ident.clone()))
@@ -726,10 +1832,10 @@ impl Lowerer {
         };
 
         let numerator = Expr::Op {
-            op: '-',
+            op: Op::Sub,
             inputs: vec![
                 Expr::Op {
-                    op: '+',
+                    op: Op::Add,
                     inputs: vec![
                         Expr::Ident(base_bound_ident.clone()),
                         tile_width_expr.clone(),
@@ -740,7 +1846,7 @@ impl Lowerer {
         };
 
         Expr::Op {
-            op: '/',
+            op: Op::Div,
             inputs: vec![numerator, tile_width_expr],
         }
     }
@@ -757,7 +1863,7 @@ impl Lowerer {
 
         // number of elements per iteration of base loop
         let base_loop_tile_width = Expr::Op {
-            op: '*',
+            op: Op::Mul,
             inputs: factor_loop_widths.clone(),
         };
 
@@ -781,9 +1887,9 @@ impl Lowerer {
         assert_eq!(widths.len(), iterators.len());
         let mut total_width: Vec<Expr> = widths
             .into_iter()
-            .zip(iterators.into_iter())
+            .zip(iterators)
             .map(|(width, iterator)| Expr::Op {
-                op: '*',
+                op: Op::Mul,
                 inputs: vec![width, iterator],
             })
             .collect();
@@ -791,7 +1897,7 @@ impl Lowerer {
         total_width.push(ultimate_iterator);
 
         let reconstructed_index = Expr::Op {
-            op: '+',
+            op: Op::Add,
             inputs: total_width,
         };
 
@@ -808,30 +1914,294 @@ impl Lowerer {
         ]
     }
 
-    fn create_affine_index(indices: Vec<String>, bounds: Vec<String>) -> Expr {
+    /// Load an element of an operand store, whose dimensions have bounds `dim_bounds`, at the
+    /// `iterators` of its indices. An affine dimension like `(2i+k)` is iterated at `2 * i + k`,
+    /// and where it's out of bounds, read according to the operand's boundary mode.
+    fn create_operand_load(
+        ident: &str,
+        index: &str,
+        dim_bounds: &[String],
+        bound_idents: &HashMap<char, String>,
+        iterators: &HashMap<char, Expr>,
+        dtype: DType,
+    ) -> Expr {
+        let op = |op: Op, inputs: Vec<Expr>| Expr::Op { op, inputs };
+        let boundary = index::boundary(index);
+        let mut guards = vec![];
+        let (positions, bounds) = index::dims(index)
+            .into_iter()
+            .zip(dim_bounds.iter())
+            .map(|(dim, dim_bound)| {
+                if let Some(c) = dim.plain() {
+                    return (iterators[&c].clone(), bound_idents[&c].clone());
+                }
+                // a broadcast dimension of extent 1 is read with a stride of 0
+                if dim.broadcast {
+                    let position = op(
+                        Op::Select,
+                        vec![
+                            op(Op::Eq, vec![Expr::Ident(dim_bound.clone()), Expr::Int(1)]),
+                            Expr::Int(0),
+                            iterators[&dim.terms[0].1].clone(),
+                        ],
+                    );
+                    return (position, dim_bound.clone());
+                }
+                // a merged dimension is read at the flat position of its indices
+                if dim.merge.is_some() {
+                    let position = Self::create_affine_index(
+                        dim.terms
+                            .iter()
+                            .map(|(_, c)| iterators[c].clone())
+                            .collect(),
+                        dim.terms
+                            .iter()
+                            .map(|(_, c)| bound_idents[c].clone())
+                            .collect(),
+                    );
+                    return (position, dim_bound.clone());
+                }
+                let mut terms: Vec<Expr> = dim
+                    .terms
+                    .iter()
+                    .map(|(coefficient, c)| {
+                        let iterator = iterators[c].clone();
+                        match coefficient {
+                            1 => iterator,
+                            _ => op(Op::Mul, vec![Expr::Int(*coefficient), iterator]),
+                        }
+                    })
+                    .collect();
+                // the position is `p - m`, with `p` and the margin `m` kept unsigned
+                if dim.offset > 0 {
+                    terms.push(Expr::Int(dim.offset as usize));
+                }
+                let p = match terms.len() {
+                    1 => terms.remove(0),
+                    _ => op(Op::Add, terms),
+                };
+                let m = (-dim.offset).max(0) as usize;
+                let n = Expr::Ident(dim_bound.clone());
+                let shift = |expr: Expr, by: usize| match by {
+                    0 => expr,
+                    _ => op(Op::Add, vec![expr, Expr::Int(by)]),
+                };
+                let unshift = |expr: Expr| match m {
+                    0 => expr,
+                    _ => op(Op::Sub, vec![expr, Expr::Int(m)]),
+                };
+                // only the branch taken is evaluated, so out of bounds positions never underflow
+                let below = op(Op::Lt, vec![p.clone(), Expr::Int(m)]);
+                let within = op(Op::Lt, vec![p.clone(), shift(n.clone(), m)]);
+                let position = match boundary {
+                    None => p,
+                    Some(Boundary::Zero) => {
+                        if m > 0 {
+                            guards.push(op(Op::Ge, vec![p.clone(), Expr::Int(m)]));
+                        }
+                        guards.push(within);
+                        unshift(p)
+                    }
+                    Some(Boundary::Clamp) => unshift(op(
+                        Op::Min,
+                        vec![
+                            op(Op::Max, vec![p, Expr::Int(m)]),
+                            op(Op::Sub, vec![shift(n, m), Expr::Int(1)]),
+                        ],
+                    )),
+                    // from the opposite edge, for positions up to one extent out
+                    Some(Boundary::Wrap) => op(
+                        Op::Select,
+                        vec![
+                            below,
+                            unshift(op(Op::Add, vec![p.clone(), n.clone()])),
+                            op(
+                                Op::Select,
+                                vec![within, unshift(p.clone()), op(Op::Sub, vec![unshift(p), n])],
+                            ),
+                        ],
+                    ),
+                    // about the edge element, for positions up to one extent out
+                    Some(Boundary::Mirror) => op(
+                        Op::Select,
+                        vec![
+                            below,
+                            op(Op::Sub, vec![Expr::Int(m), p.clone()]),
+                            op(
+                                Op::Select,
+                                vec![
+                                    within,
+                                    unshift(p.clone()),
+                                    op(
+                                        Op::Sub,
+                                        vec![
+                                            shift(op(Op::Mul, vec![Expr::Int(2), n]), m),
+                                            Expr::Int(2),
+                                            p,
+                                        ],
+                                    ),
+                                ],
+                            ),
+                        ],
+                    ),
+                };
+                (position, dim_bound.clone())
+            })
+            .unzip();
+        let load = Expr::Indexed {
+            ident: ident.to_string(),
+            index: Box::new(Self::create_affine_index(positions, bounds)),
+        };
+        // zero padding reads 0 outside the bounds of any dimension
+        guards.into_iter().rev().fold(load, |load, guard| {
+            op(Op::Select, vec![guard, load, Expr::Literal(0., dtype)])
+        })
+    }
+
+    /// The extent of an index from that of a dimension `dim` it's in with coefficient `stride`,
+    /// and the (coefficient, extent) of the dimension's other terms
+    fn create_solved_extent(dim: Expr, stride: usize, terms: Vec<(usize, Expr)>) -> Expr {
+        let span = Expr::Op {
+            op: Op::Sub,
+            inputs: [
+                vec![dim, Expr::Int(1)],
+                terms
+                    .into_iter()
+                    .map(|(coefficient, extent)| {
+                        let extent = Expr::Op {
+                            op: Op::Sub,
+                            inputs: vec![extent, Expr::Int(1)],
+                        };
+                        match coefficient {
+                            1 => extent,
+                            _ => Expr::Op {
+                                op: Op::Mul,
+                                inputs: vec![Expr::Int(coefficient), extent],
+                            },
+                        }
+                    })
+                    .collect(),
+            ]
+            .concat(),
+        };
+        let span = match stride {
+            1 => span,
+            _ => Expr::Op {
+                op: Op::Div,
+                inputs: vec![span, Expr::Int(stride)],
+            },
+        };
+        Expr::Op {
+            op: Op::Add,
+            inputs: vec![span, Expr::Int(1)],
+        }
+    }
+
+    /// The product of a set of `extents`
+    fn create_product_extent(mut extents: Vec<Expr>) -> Expr {
+        match extents.len() {
+            0 => Expr::Int(1),
+            1 => extents.remove(0),
+            _ => Expr::Op {
+                op: Op::Mul,
+                inputs: extents,
+            },
+        }
+    }
+
+    /// The largest of a set of `extents`
+    fn create_max_extent(mut extents: Vec<Expr>) -> Expr {
+        let first = extents.remove(0);
+        extents.into_iter().fold(first, |max, extent| Expr::Op {
+            op: Op::Max,
+            inputs: vec![max, extent],
+        })
+    }
+
+    /// The sum of a set of `extents` and a constant `offset`
+    fn create_sum_extent(extents: Vec<Expr>, offset: i64) -> Expr {
+        let sum = match extents.len() {
+            0 => return Expr::Int(offset.max(0) as usize),
+            1 => extents.into_iter().next().unwrap(),
+            _ => Expr::Op {
+                op: Op::Add,
+                inputs: extents,
+            },
+        };
+        match offset {
+            0 => sum,
+            1.. => Expr::Op {
+                op: Op::Add,
+                inputs: vec![sum, Expr::Int(offset as usize)],
+            },
+            _ => Expr::Op {
+                op: Op::Sub,
+                inputs: vec![sum, Expr::Int(-offset as usize)],
+            },
+        }
+    }
+
+    /// The expression of an `extent` in terms of the program's input dimensions
+    fn create_extent_expr(extent: &Extent) -> Expr {
+        match extent {
+            Extent::Dim(input, dim) => Expr::Indexed {
+                ident: format!("d{input}"),
+                index: Box::new(Expr::Int(*dim)),
+            },
+            Extent::Solved { dim, stride, terms } => Self::create_solved_extent(
+                Self::create_extent_expr(dim),
+                *stride,
+                terms
+                    .iter()
+                    .map(|(coefficient, extent)| (*coefficient, Self::create_extent_expr(extent)))
+                    .collect(),
+            ),
+            Extent::Sum { extents, offset } => Self::create_sum_extent(
+                extents.iter().map(Self::create_extent_expr).collect(),
+                *offset,
+            ),
+            Extent::Product(extents) => {
+                Self::create_product_extent(extents.iter().map(Self::create_extent_expr).collect())
+            }
+            Extent::Max(extents) => {
+                Self::create_max_extent(extents.iter().map(Self::create_extent_expr).collect())
+            }
+            Extent::Quotient { dim, divisors } => Expr::Op {
+                op: Op::Div,
+                inputs: vec![
+                    Self::create_extent_expr(dim),
+                    Self::create_product_extent(
+                        divisors.iter().map(Self::create_extent_expr).collect(),
+                    ),
+                ],
+            },
+        }
+    }
+
+    fn create_affine_index(indices: Vec<Expr>, bounds: Vec<String>) -> Expr {
         let d = indices.len();
         let mut sum_expr = None;
-        for k in 0..d {
+        for (k, index) in indices.into_iter().enumerate() {
             let mut product_expr = None;
-            for m in (k + 1)..d {
+            for bound in &bounds[k + 1..d] {
                 product_expr = Some(match product_expr {
                     Some(expr) => Expr::Op {
-                        op: '*',
-                        inputs: vec![expr, Expr::Ident(bounds[m].clone())],
+                        op: Op::Mul,
+                        inputs: vec![expr, Expr::Ident(bound.clone())],
                     },
-                    None => Expr::Ident(bounds[m].clone()),
+                    None => Expr::Ident(bound.clone()),
                 });
             }
             let partial_expr = match product_expr {
                 Some(expr) => Expr::Op {
-                    op: '*',
-                    inputs: vec![Expr::Ident(indices[k].clone()), expr],
+                    op: Op::Mul,
+                    inputs: vec![index, expr],
                 },
-                None => Expr::Ident(indices[k].clone()),
+                None => index,
             };
             sum_expr = Some(match sum_expr {
                 Some(expr) => Expr::Op {
-                    op: '+',
+                    op: Op::Add,
                     inputs: vec![expr, partial_expr],
                 },
                 None => partial_expr,
@@ -853,11 +2223,17 @@ impl Lowerer {
                 _ => false,
             }) {
                 let incoming_mutable = match &arg.type_ {
-                    Type::Int(m) | Type::Array(m) | Type::ArrayRef(m) => *m,
+                    Type::Int(m)
+                    | Type::Scalar(m, _)
+                    | Type::Array(m, _)
+                    | Type::ArrayRef(m, _) => *m,
                 };
                 if incoming_mutable {
                     match &mut existing.type_ {
-                        Type::Int(em) | Type::Array(em) | Type::ArrayRef(em) => *em = true,
+                        Type::Int(em)
+                        | Type::Scalar(em, _)
+                        | Type::Array(em, _)
+                        | Type::ArrayRef(em, _) => *em = true,
                     }
                 }
             } else {
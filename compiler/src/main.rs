@@ -1,26 +1,21 @@
-mod ast;
-mod backend;
-mod block;
-mod graph;
-mod lowerer;
-mod parser;
-mod tokenizer;
-
-use backend::cuda::CudaBackend;
-
-use crate::backend::block::BlockBackend;
-use crate::backend::rust::RustBackend;
-use crate::backend::Render;
-use crate::graph::Graph;
-use crate::lowerer::Lowerer;
-use crate::parser::Parser;
+use compiler::ast::DType;
+use compiler::backend::block::BlockBackend;
+use compiler::backend::cuda::CudaBackend;
+use compiler::backend::rust::RustBackend;
+use compiler::backend::Render;
+use compiler::cache::Cache;
+use compiler::graph::Graph;
+use compiler::lowerer::Lowerer;
+use compiler::parser::Parser;
+use compiler::tempdir::TempDir;
 
 use std::io::Read;
 use std::{env, fs, io, process::Command};
 
 // Formats Rust code using rustfmt
 fn format_rust_code(code: String) -> String {
-    let path = "/tmp/tmp.rs";
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("tmp.rs");
     fs::write(&path, code).unwrap();
     Command::new("rustfmt").arg(&path).status().unwrap();
     fs::read_to_string(&path).unwrap()
@@ -28,12 +23,17 @@ fn format_rust_code(code: String) -> String {
 
 fn main() -> Result<(), String> {
     let args: Vec<String> = env::args().collect();
+    if args.get(1).is_some_and(|arg| arg == "cache") {
+        return cache_command(&args[2..]);
+    }
 
     // Parse command-line arguments
     let mut input_path: Option<String> = None;
     let mut output_path: Option<String> = None;
     let mut source = "i";
     let mut target = "rust";
+    let mut dtypes: Vec<DType> = vec![];
+    let mut wide_accumulation = false;
 
     let mut iter = args.iter().skip(1); // Skip the program name
     while let Some(arg) = iter.next() {
@@ -44,6 +44,15 @@ fn main() -> Result<(), String> {
             "-t" | "--target" => {
                 target = iter.next().ok_or("Error: Missing value for --target")?;
             }
+            "-d" | "--dtype" => {
+                dtypes = iter
+                    .next()
+                    .ok_or("Error: Missing value for --dtype")?
+                    .split(',')
+                    .map(|dtype| dtype.parse::<DType>().map_err(|e| format!("Error: {e}")))
+                    .collect::<Result<_, _>>()?;
+            }
+            "-w" | "--wide-accumulation" => wide_accumulation = true,
             "-h" | "--help" => {
                 print_help();
                 return Ok(());
@@ -60,7 +69,7 @@ fn main() -> Result<(), String> {
     }
 
     // Validate the target platform
-    if !(target == "rust" || target == "ir" || target == "cuda") {
+    if !(target == "rust" || target == "ir" || target == "cuda" || target == "dylib") {
         return Err(format!("Error: Unsupported target '{}'", target));
     }
 
@@ -82,21 +91,59 @@ fn main() -> Result<(), String> {
     // Process the input
     let block = match source {
         "i" => {
-            let (_ast, expr_bank) = Parser::new(&input)?.parse().unwrap();
-            let graph = Graph::from_expr_bank(&expr_bank);
+            let (ast, expr_bank) = Parser::new(&input)?
+                .parse()
+                .map_err(|e| format!("Error: {e}"))?;
+            let mut graph = Graph::from_expr_bank(&expr_bank);
+
+            // signatures give the static shapes of the first inputs
+            graph
+                .set_leaf_shapes(&ast.2)
+                .map_err(|e| format!("Error: {e}"))?;
+
+            // a single dtype applies to every input but positions
+            match dtypes.len() {
+                0 => {}
+                1 => graph.set_value_leaf_dtypes(dtypes[0]),
+                _ => graph
+                    .set_leaf_dtypes(&dtypes)
+                    .map_err(|e| format!("Error: {e}"))?,
+            }
 
             // get IndexExpr
-            let crate::ast::Expr::Index(_) = expr_bank.0[0] else {
+            let compiler::ast::Expr::Index(_) = expr_bank.0[0] else {
                 panic!("expression is not of variant Index")
             };
 
             // lower
-            Lowerer::new().lower(&graph)
+            Lowerer::new()
+                .wide_accumulation(wide_accumulation)
+                .lower(&graph)
         }
-        "ir" => block::parser::parse(&input),
+        "ir" => compiler::block::parser::parse(&input),
         &_ => unreachable!(),
     };
 
+    // a library is built from the Rust rendering rather than printed
+    if target == "dylib" {
+        let path = match output_path.as_deref() {
+            Some(path) if path != "-" => path,
+            _ => return Err("Error: Missing output file for target 'dylib'".to_string()),
+        };
+        let copied = match Cache::from_env()
+            .build::<RustBackend, _>(&block, |dylib_path| Ok(fs::copy(dylib_path, path)))
+        {
+            Ok(copied) => copied,
+            // the compiler's diagnostics are printed as they are
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+        copied.map_err(|e| format!("Failed to write output file: {}", e))?;
+        return Ok(());
+    }
+
     let formatted_code = match target {
         "rust" => format_rust_code(RustBackend::render(&block)),
         "ir" => BlockBackend::render(&block),
@@ -119,15 +166,82 @@ fn main() -> Result<(), String> {
     Ok(())
 }
 
+// Inspects or clears the kernel cache
+fn cache_command(args: &[String]) -> Result<(), String> {
+    let cache = Cache::from_env();
+    match args.iter().map(String::as_str).collect::<Vec<_>>()[..] {
+        [] | ["list"] => {
+            let entries = cache
+                .entries()
+                .map_err(|e| format!("Failed to read the cache: {}", e))?;
+            println!("{}", cache.dir().display());
+            for entry in &entries {
+                let age = entry.used.elapsed().unwrap_or_default().as_secs();
+                println!(
+                    "{}  {:>10}  used {} ago",
+                    entry.key,
+                    format_size(entry.size),
+                    format_age(age)
+                );
+            }
+            let size = entries.iter().map(|entry| entry.size).sum();
+            println!(
+                "{} entries, {} of {}",
+                entries.len(),
+                format_size(size),
+                format_size(cache.max_size())
+            );
+        }
+        ["clear"] => {
+            let n_entries = cache
+                .clear()
+                .map_err(|e| format!("Failed to clear the cache: {}", e))?;
+            println!("Removed {n_entries} entries from {}", cache.dir().display());
+        }
+        _ => return Err(format!("Error: Unknown cache command '{}'", args.join(" "))),
+    }
+    Ok(())
+}
+
+fn format_size(bytes: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < units.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+    format!("{size:.1} {}", units[unit])
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
+
 // Prints the help message
 fn print_help() {
     println!(
         r#"Usage: ic [OPTIONS] [INPUT] [OUTPUT]
+       ic cache [list|clear]
 
 Options:
-  -t, --target <TARGET>  Specify the target platform (default: rust)
+  -t, --target <TARGET>  Specify the target platform: rust, cuda, ir, or dylib to build a
+                         shared library into OUTPUT (default: rust)
+  -d, --dtype <DTYPES>   Input element types, one for all inputs or a comma-separated list
+                         (f32, f64, f16, bf16, i32, bool; default: f32)
+  -w, --wide-accumulation
+                         Accumulate reductions in a wider type (f64 for f32, f32 for f16/bf16)
   -h, --help             Print this help message
 
+Commands:
+  cache [list|clear]     List or remove the built kernels in the cache, $ILANG_CACHE_DIR
+                         (default: ~/.cache/ilang), of at most $ILANG_CACHE_SIZE bytes
+
 Arguments:
   INPUT                  Path to the input file (use '-' for STDIN)
   OUTPUT                 Path to the output file (use '-' for STDOUT)"#
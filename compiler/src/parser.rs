@@ -2,9 +2,10 @@ use std::collections::HashMap;
 use std::fmt;
 
 use crate::ast::{
-    BinaryOp, Combinator, Expr, ExprBank, ExprRef, IndexExpr, NamedExpr, NoOp, ScalarOp, Schedule,
-    Symbol, UnaryOp, AST,
+    Combinator, Expr, ExprBank, ExprRef, IndexExpr, NamedExpr, NoOp, Op, ScalarOp, Schedule,
+    Signature, Symbol, AST,
 };
+use crate::index;
 use crate::tokenizer::{Token, Tokenizer};
 
 #[derive(Debug)]
@@ -43,39 +44,64 @@ impl<'a> Parser<'a> {
 
     pub fn parse(&mut self) -> Result<(AST, ExprBank), ParseError> {
         let mut named_exprs = vec![];
+        let mut signatures = vec![];
         let mut expr_bank = ExprBank(Vec::new());
         while let Token::Colon = self.tokenizer.peek()[1] {
-            let named_expr = self.parse_named_expr(&mut expr_bank)?;
-            named_exprs.push(named_expr);
+            let ident = self.parse_symbol()?;
+            self.tokenizer.next(); // the Colon
+            match self.tokenizer.peek()[0] {
+                Token::Signature(_) => signatures.push(self.parse_signature(ident)?),
+                _ => named_exprs.push(self.parse_named_expr(ident, &mut expr_bank)?),
+            }
         }
         // parse final (non-named) expression
         let expr = self.parse_expr()?;
         expr_bank.0.push(expr);
-        Ok((AST(named_exprs, ExprRef(expr_bank.0.len() - 1)), expr_bank))
+        Ok((
+            AST(named_exprs, ExprRef(expr_bank.0.len() - 1), signatures),
+            expr_bank,
+        ))
     }
 
-    fn parse_named_expr(&mut self, expr_bank: &mut ExprBank) -> Result<NamedExpr, ParseError> {
-        let ident = self.parse_symbol()?;
-        match self.tokenizer.next() {
-            Token::Colon => {
-                let expr = self.parse_expr()?;
-                expr_bank.0.push(expr);
-                let expr_ref = ExprRef(expr_bank.0.len() - 1);
-                self.symbol_table.insert(ident.clone(), expr_ref);
-                Ok(NamedExpr { ident, expr_ref })
-            }
-            _ => Err(ParseError::InvalidToken {
-                expected: "Colon".to_string(),
-            }),
-        }
+    fn parse_named_expr(
+        &mut self,
+        ident: Symbol,
+        expr_bank: &mut ExprBank,
+    ) -> Result<NamedExpr, ParseError> {
+        let expr = self.parse_expr()?;
+        expr_bank.0.push(expr);
+        let expr_ref = ExprRef(expr_bank.0.len() - 1);
+        self.symbol_table.insert(ident.clone(), expr_ref);
+        Ok(NamedExpr { ident, expr_ref })
+    }
+
+    /// Parse the shape of an input, its dimensions' extents or `_` for one known at run time
+    fn parse_signature(&mut self, ident: Symbol) -> Result<Signature, ParseError> {
+        let Token::Signature(s) = self.tokenizer.next() else {
+            unreachable!()
+        };
+        let shape = s
+            .split(',')
+            .map(|extent| match extent.trim() {
+                "_" => Ok(None),
+                extent => match extent.parse::<usize>() {
+                    Ok(extent) => Ok(Some(extent)),
+                    Err(_) => Err(ParseError::InvalidToken {
+                        expected: format!("an extent or '_' in signature [{s}]"),
+                    }),
+                },
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Signature { ident, shape })
     }
 
     fn parse_expr(&mut self) -> Result<Expr, ParseError> {
         match self.tokenizer.peek() {
             [_, Token::Dot] => Ok(Expr::Combinator(self.parse_combinator()?)),
-            [Token::Operator(_), _] | [_, Token::Operator(_)] | [_, Token::Squiggle] => {
-                Ok(Expr::Index(self.parse_index_expr()?))
-            }
+            [Token::Operator(_), _]
+            | [_, Token::Operator(_)]
+            | [_, Token::Squiggle]
+            | [Token::Symbol(_), Token::Symbol(_)] => Ok(Expr::Index(self.parse_index_expr()?)),
             _ => Err(ParseError::InvalidToken {
                 expected: "Index or Dot".to_string(),
             }),
@@ -89,13 +115,12 @@ impl<'a> Parser<'a> {
                 let splits = self.parse_splits()?;
                 let (loop_order, compute_levels) = self.parse_loop_order()?;
                 Ok(IndexExpr {
-                    op: index_expr.op,
-                    out: index_expr.out,
                     schedule: Schedule {
                         splits: splits,
                         loop_order: loop_order,
                         compute_levels: compute_levels,
                     },
+                    ..index_expr
                 })
             }
             _ => Ok(index_expr),
@@ -105,21 +130,80 @@ impl<'a> Parser<'a> {
     fn parse_unscheduled_index_expr(&mut self) -> Result<IndexExpr, ParseError> {
         let scalarop = self.parse_scalarop()?;
         match self.tokenizer.next() {
-            Token::Squiggle => Ok(IndexExpr {
-                op: scalarop,
-                out: self.parse_symbol()?,
-                schedule: Schedule {
-                    splits: HashMap::new(),
-                    loop_order: vec![],
-                    compute_levels: vec![],
-                },
-            }),
+            Token::Squiggle => {
+                let (out, scan) = self.parse_scan_symbol()?;
+                // an arg reduction has a position to find
+                if let ScalarOp::UnaryOp(op @ (Op::ArgMax | Op::ArgMin), input) = &scalarop {
+                    let out = index::chars(&out.0);
+                    if index::chars(&input.0).iter().all(|c| out.contains(c)) {
+                        return Err(ParseError::InvalidToken {
+                            expected: format!("a reduced index for op [{op}]"),
+                        });
+                    }
+                }
+                Ok(IndexExpr {
+                    op: scalarop,
+                    out,
+                    scan,
+                    schedule: Schedule {
+                        splits: HashMap::new(),
+                        loop_order: vec![],
+                        compute_levels: vec![],
+                    },
+                })
+            }
             _ => Err(ParseError::InvalidToken {
                 expected: "Squiggle".to_string(),
             }),
         }
     }
 
+    /// Parse an output index, which may mark one index as scanned with a preceding `\` and merge
+    /// indices into one dimension, e.g. `(ij)`
+    fn parse_scan_symbol(&mut self) -> Result<(Symbol, Option<char>), ParseError> {
+        let Token::Symbol(s) = self.tokenizer.next() else {
+            return Err(ParseError::InvalidToken {
+                expected: "Symbol".to_string(),
+            });
+        };
+        match s.split_once('\\') {
+            None => Ok((Symbol(s), None)),
+            Some((left, right)) => match right.chars().next() {
+                Some(c) if c.is_alphabetic() && !right.contains('\\') => {
+                    Ok((Symbol(format!("{left}{right}")), Some(c)))
+                }
+                _ => Err(ParseError::InvalidToken {
+                    expected: "Index after scan marker".to_string(),
+                }),
+            },
+        }
+        .and_then(|(out, scan)| match index::parse(&out.0) {
+            Ok((dims, None))
+                if dims.iter().all(|dim| {
+                    dim.plain().is_some()
+                        || dim
+                            .merge
+                            .as_ref()
+                            .is_some_and(|merge| merge.iter().all(Option::is_none))
+                }) =>
+            {
+                Ok((out, scan))
+            }
+            _ => Err(ParseError::InvalidToken {
+                expected: "Output index of single or merged indices".to_string(),
+            }),
+        })
+    }
+
+    /// Parse an operand index, whose dimensions may be affine in the indices, e.g. `(i+k)j`
+    fn parse_operand(&mut self) -> Result<Symbol, ParseError> {
+        let symbol = self.parse_symbol()?;
+        match index::parse(&symbol.0) {
+            Ok(_) => Ok(symbol),
+            Err(e) => Err(ParseError::InvalidToken { expected: e }),
+        }
+    }
+
     fn parse_splits(&mut self) -> Result<HashMap<char, Vec<usize>>, ParseError> {
         // Skip the initial Bar token
         self.tokenizer.next();
@@ -248,48 +332,82 @@ impl<'a> Parser<'a> {
 
     fn parse_scalarop(&mut self) -> Result<ScalarOp, ParseError> {
         match self.tokenizer.peek() {
-            [Token::Operator(_), _] => Ok(ScalarOp::UnaryOp(self.parse_unaryop()?)),
-            [Token::Symbol(_), Token::Operator(_)] => {
-                Ok(ScalarOp::BinaryOp(self.parse_binaryop()?))
-            }
+            [Token::Operator(_), _] => self.parse_unaryop(),
+            [Token::Symbol(_), Token::Operator(_)] => self.parse_binaryop(),
+            [Token::Symbol(_), Token::Symbol(_)] => self.parse_namedop(),
             [Token::Symbol(_), Token::Squiggle] => Ok(ScalarOp::NoOp(self.parse_noop()?)),
             _ => Err(ParseError::InvalidToken {
-                expected: "[Operator]<Any>, [Symbol][Operator], [Symbol]<Any>".to_string(),
+                expected: "[Operator]<Any>, [Symbol][Operator], [Symbol][Symbol], [Symbol]<Any>"
+                    .to_string(),
             }),
         }
     }
 
-    fn parse_binaryop(&mut self) -> Result<BinaryOp, ParseError> {
-        let left = self.parse_symbol()?;
-        match self.tokenizer.next() {
-            Token::Operator('*') => Ok(BinaryOp::Mul(left, self.parse_symbol()?)),
-            Token::Operator('/') => Ok(BinaryOp::Div(left, self.parse_symbol()?)),
-            Token::Operator('+') => Ok(BinaryOp::Add(left, self.parse_symbol()?)),
-            Token::Operator('-') => Ok(BinaryOp::Sub(left, self.parse_symbol()?)),
-            Token::Operator('>') => Ok(BinaryOp::Max(left, self.parse_symbol()?)),
-            _ => Err(ParseError::InvalidToken {
-                expected: "Operator".to_string(),
-            }),
-        }
+    fn parse_binaryop(&mut self) -> Result<ScalarOp, ParseError> {
+        let left = self.parse_operand()?;
+        let op = match self.tokenizer.next() {
+            Token::Operator('*') => Op::Mul,
+            Token::Operator('/') => Op::Div,
+            Token::Operator('+') => Op::Add,
+            Token::Operator('-') => Op::Sub,
+            Token::Operator('>') => Op::Max,
+            Token::Operator('<') => Op::Min,
+            Token::Operator('^') => Op::Pow,
+            _ => {
+                return Err(ParseError::InvalidToken {
+                    expected: "Operator".to_string(),
+                })
+            }
+        };
+        Ok(ScalarOp::BinaryOp(op, left, self.parse_operand()?))
     }
 
-    fn parse_unaryop(&mut self) -> Result<UnaryOp, ParseError> {
-        match self.tokenizer.next() {
-            Token::Operator('*') => Ok(UnaryOp::Prod(self.parse_symbol()?)),
-            Token::Operator('+') => Ok(UnaryOp::Accum(self.parse_symbol()?)),
-            Token::Operator('>') => Ok(UnaryOp::Relu(self.parse_symbol()?)),
-            Token::Operator('-') => Ok(UnaryOp::Neg(self.parse_symbol()?)),
-            Token::Operator('/') => Ok(UnaryOp::Recip(self.parse_symbol()?)),
-            Token::Operator('^') => Ok(UnaryOp::Exp(self.parse_symbol()?)),
-            Token::Operator('$') => Ok(UnaryOp::Log(self.parse_symbol()?)),
+    fn parse_unaryop(&mut self) -> Result<ScalarOp, ParseError> {
+        let op = match self.tokenizer.next() {
+            Token::Operator('*') => Op::Mul,
+            Token::Operator('+') => Op::Add,
+            Token::Operator('>') => Op::Max,
+            Token::Operator('<') => Op::Min,
+            Token::Operator('-') => Op::Sub,
+            Token::Operator('/') => Op::Div,
+            Token::Operator('^') => Op::Exp,
+            Token::Operator('$') => Op::Log,
+            _ => {
+                return Err(ParseError::InvalidToken {
+                    expected: "Operator".to_string(),
+                })
+            }
+        };
+        Ok(ScalarOp::UnaryOp(op, self.parse_operand()?))
+    }
+
+    /// Parse a prefix op by name, e.g. `sqrt ij~ij`, `pow ij j~ij` or `where ij ij ij~ij`
+    fn parse_namedop(&mut self) -> Result<ScalarOp, ParseError> {
+        let name = self.parse_symbol()?;
+        let op = name.0.parse::<Op>().map_err(|_| ParseError::InvalidToken {
+            expected: "Op name".to_string(),
+        })?;
+        let mut inputs = vec![];
+        while let Token::Symbol(_) = self.tokenizer.peek()[0] {
+            inputs.push(self.parse_operand()?);
+        }
+        match (op.arities().contains(&inputs.len()), inputs.as_slice()) {
+            (true, [in0]) => Ok(ScalarOp::UnaryOp(op, in0.clone())),
+            (true, [in0, in1]) => Ok(ScalarOp::BinaryOp(op, in0.clone(), in1.clone())),
+            (true, [in0, in1, in2]) => Ok(ScalarOp::TernaryOp(
+                op,
+                in0.clone(),
+                in1.clone(),
+                in2.clone(),
+            )),
             _ => Err(ParseError::InvalidToken {
-                expected: "Operator".to_string(),
+                expected: format!("{:?} inputs to '{}'", op.arities(), name.0),
             }),
         }
     }
 
     fn parse_noop(&mut self) -> Result<NoOp, ParseError> {
-        Ok(NoOp(self.parse_symbol()?))
+        Ok(NoOp(self.parse_operand()?))
     }
 
     fn parse_combinator(&mut self) -> Result<Combinator, ParseError> {
@@ -318,7 +436,7 @@ impl<'a> Parser<'a> {
 
     fn parse_symbol(&mut self) -> Result<Symbol, ParseError> {
         match self.tokenizer.next() {
-            Token::Symbol(s) => Ok(Symbol(s)),
+            Token::Symbol(s) if !s.contains('\\') => Ok(Symbol(s)),
             _ => Err(ParseError::InvalidToken {
                 expected: "Symbol".to_string(),
             }),
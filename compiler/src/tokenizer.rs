@@ -10,6 +10,7 @@ pub enum Token {
     Bar,
     Int(String),
     Operator(char),
+    Signature(String), // the contents of a bracketed shape, e.g. `128, _` of `[128, _]`
     EOF,
 }
 
@@ -24,6 +25,7 @@ impl fmt::Display for Token {
             Token::Bar => write!(f, "[|]"),
             Token::Int(s) => write!(f, "[{}]", s),
             Token::Operator(op) => write!(f, "Operator [{}]", op),
+            Token::Signature(s) => write!(f, "[[{}]]", s),
             Token::EOF => write!(f, "[EOF]"),
         }
     }
@@ -97,7 +99,14 @@ impl<'a> Tokenizer<'a> {
             return Ok(Token::Int(self.consume_int()));
         }
 
-        if c.is_alphabetic() || c.is_numeric() || c == '_' || c == '(' || c == ')' || c == ',' {
+        if c.is_alphabetic()
+            || c.is_numeric()
+            || c == '_'
+            || c == '('
+            || c == ')'
+            || c == ','
+            || c == '\\'
+        {
             return Ok(Token::Symbol(self.consume_str()));
         }
 
@@ -122,7 +131,20 @@ impl<'a> Tokenizer<'a> {
                 self.consume_char();
                 Ok(Token::Bar)
             }
-            '+' | '*' | '>' | '/' | '-' | '^' | '$' => {
+            '[' => {
+                self.consume_char();
+                let start = self.pos;
+                while self.pos < self.input.len() && self.peek_char() != ']' {
+                    self.consume_char();
+                }
+                if self.pos >= self.input.len() {
+                    return Err("Unclosed signature".to_string());
+                }
+                let signature = self.input[start..self.pos].to_string();
+                self.consume_char();
+                Ok(Token::Signature(signature))
+            }
+            '+' | '*' | '>' | '<' | '/' | '-' | '^' | '$' => {
                 self.consume_char();
                 Ok(Token::Operator(c))
             }
@@ -154,15 +176,28 @@ impl<'a> Tokenizer<'a> {
 
     fn consume_str(&mut self) -> String {
         let start = self.pos;
+        // a `+` or `-` inside parentheses is an affine index term, e.g. `(i+k-1)`, and a `:` or
+        // `-` inside brackets or braces a slice or padding, e.g. `i[1:-1]`
+        let mut depth = 0;
         while self.pos < self.input.len()
             && (self.peek_char().is_alphabetic()
+                || (matches!(self.peek_char(), '+' | '-' | ':') && depth > 0)
+                || matches!(self.peek_char(), '[' | ']' | '{' | '}')
+                || self.peek_char() == '@'
+                || self.peek_char() == '?'
                 || self.peek_char().is_numeric()
                 || self.peek_char() == '_'
                 || self.peek_char() == '\''
                 || self.peek_char() == '('
                 || self.peek_char() == ')'
-                || self.peek_char() == ',')
+                || self.peek_char() == ','
+                || self.peek_char() == '\\')
         {
+            match self.peek_char() {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
             self.consume_char();
         }
         self.input[start..self.pos].to_string()
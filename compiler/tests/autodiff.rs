@@ -0,0 +1,326 @@
+mod common;
+
+use common::{assert_close, exec, graph, Array, Kernel};
+
+#[test]
+fn composed_grad_keeps_input_order() {
+    // the gradient of eˣ, dy eˣ, takes x then the seed dy
+    let grad = graph("^i~i").grad(&[0]).unwrap();
+    let outputs = exec(
+        &grad.chain(&graph("-i~i")),
+        &[(vec![0.], vec![1]), (vec![2.], vec![1])],
+    );
+    assert_close(&outputs[0].0, &[-2.], "chained");
+
+    let outputs = exec(
+        &graph("-i~i").chain(&grad),
+        &[(vec![1.], vec![1]), (vec![2.], vec![1])],
+    );
+    assert_close(&outputs[0].0, &[2. * (-1f64).exp()], "composed");
+
+    let outputs = exec(
+        &grad.fanout(&graph("-i~i")),
+        &[(vec![0.], vec![1]), (vec![2.], vec![1])],
+    );
+    assert_close(&outputs[0].0, &[2.], "fanned out");
+    assert_close(&outputs[1].0, &[0.], "fanned out");
+}
+
+#[test]
+fn product_gradient_with_zeros() {
+    let grad = graph("*ij~i").grad(&[0]).unwrap();
+    for (x, expected) in [
+        (vec![2., 0., 4.], vec![0., 8., 0.]),
+        (vec![2., 0., 0.], vec![0., 0., 0.]),
+        (vec![2., 3., 4.], vec![12., 8., 6.]),
+    ] {
+        let outputs = exec(&grad, &[(x, vec![1, 3]), (vec![1.], vec![1])]);
+        assert_close(&outputs[0].0, &expected, "gradient");
+    }
+}
+
+#[test]
+fn product_tangent_with_zeros() {
+    let jvp = graph("*ij~i").jvp().unwrap();
+    for (x, dx, expected) in [
+        (vec![2., 0.], vec![0., 1.], 2.),
+        (vec![0., 0.], vec![1., 1.], 0.),
+        (vec![2., 3.], vec![1., 1.], 5.),
+    ] {
+        let outputs = exec(&jvp, &[(x, vec![1, 2]), (dx, vec![1, 2])]);
+        assert_close(&outputs[1].0, &[expected], "tangent");
+    }
+}
+
+#[test]
+fn extreme_gradient_splits_ties() {
+    for (src, x, expected) in [
+        (">ij~i", vec![3., 3., 2.], vec![0.5, 0.5, 0.]),
+        (">ij~i", vec![1., 3., 2.], vec![0., 1., 0.]),
+        ("<ij~i", vec![2., 5., 2.], vec![0.5, 0., 0.5]),
+    ] {
+        let outputs = exec(
+            &graph(src).grad(&[0]).unwrap(),
+            &[(x, vec![1, 3]), (vec![1.], vec![1])],
+        );
+        assert_close(&outputs[0].0, &expected, src);
+    }
+}
+
+#[test]
+fn extreme_tangent_splits_ties() {
+    for (src, x, dx, expected) in [
+        (">ij~i", vec![3., 3.], vec![1., 1.], 1.),
+        (">ij~i", vec![3., 3.], vec![1., 0.], 0.5),
+        ("<ij~i", vec![1., 3.], vec![2., 5.], 2.),
+    ] {
+        let outputs = exec(
+            &graph(src).jvp().unwrap(),
+            &[(x, vec![1, 2]), (dx, vec![1, 2])],
+        );
+        assert_close(&outputs[1].0, &[expected], src);
+    }
+}
+
+#[test]
+fn infinite_seeds_leave_zero_gradients() {
+    let outputs = exec(
+        &graph(">ij~i").grad(&[0]).unwrap(),
+        &[(vec![1., 2.], vec![1, 2]), (vec![f64::INFINITY], vec![1])],
+    );
+    assert_eq!(outputs[0].0, [0., f64::INFINITY]);
+
+    // the mask picks `x` then `y`
+    let masked = graph("c: lt i i~i\nw: where i i i~i\nc.w");
+    let outputs = exec(
+        &masked.grad(&[2, 3]).unwrap(),
+        &[
+            (vec![0., 2.], vec![2]),
+            (vec![1., 1.], vec![2]),
+            (vec![3., 4.], vec![2]),
+            (vec![5., 6.], vec![2]),
+            (vec![f64::INFINITY, 1.], vec![2]),
+        ],
+    );
+    assert_eq!(outputs[0].0, [f64::INFINITY, 0.]);
+    assert_eq!(outputs[1].0, [0., 1.]);
+
+    let outputs = exec(
+        &graph(">i~i").grad(&[0]).unwrap(),
+        &[(vec![-1., 1.], vec![2]), (vec![f64::NAN, 1.], vec![2])],
+    );
+    assert_eq!(outputs[0].0, [0., 1.]);
+}
+
+#[test]
+fn infinite_tangents_leave_zero_tangents() {
+    let inf = f64::INFINITY;
+    for (src, x, dx, expected) in [
+        (">ij~i", vec![1., 2.], vec![inf, 1.], vec![1.]),
+        ("*ij~i", vec![2., 3.], vec![inf, 0.], vec![inf]),
+        (">i~i", vec![-1., 1.], vec![inf, 2.], vec![0., 2.]),
+        ("abs i~i", vec![0., -1.], vec![f64::NAN, 2.], vec![0., -2.]),
+        ("floor i~i", vec![inf, 1.5], vec![1., inf], vec![0., 0.]),
+    ] {
+        let shape = match x.len() == expected.len() {
+            true => vec![2],
+            false => vec![1, 2],
+        };
+        let outputs = exec(
+            &graph(src).jvp().unwrap(),
+            &[(x, shape.clone()), (dx, shape)],
+        );
+        assert_eq!(outputs[1].0, expected, "{src}");
+    }
+
+    // the first operand is picked then the second
+    let outputs = exec(
+        &graph("max i i~i").jvp().unwrap(),
+        &[
+            (vec![3., 0.], vec![2]),
+            (vec![1., 2.], vec![2]),
+            (vec![1., f64::NAN], vec![2]),
+            (vec![inf, 5.], vec![2]),
+        ],
+    );
+    assert_eq!(outputs[1].0, [1., 5.]);
+}
+
+#[test]
+fn undifferentiable_graphs_are_errors() {
+    assert!(graph("+i~i").grad(&[1]).is_err());
+    assert!(graph("+ij~i").grad(&[0]).is_ok());
+    assert!(graph("+i~\\i").grad(&[0]).is_err());
+    assert!(graph("*i~\\i").jvp().is_err());
+    assert!(graph("+i~\\i").jvp().is_ok());
+}
+
+/// Check the gradient of the one output of `src` with respect to the inputs `wrt`, and its
+/// Jacobian-vector product, against central differences at `inputs`
+fn check_derivatives(src: &str, inputs: &[Array], wrt: &[usize]) {
+    const H: f64 = 1e-6;
+    let f = graph(src);
+    let kernel = Kernel::build(&f);
+    let value = |inputs: &[Array]| kernel.run(inputs).remove(0);
+    // `inputs` with `direction` scaled by `h` added to input `ind`, or to all of them
+    let moved = |ind: Option<usize>, direction: &dyn Fn(usize, usize) -> f64, h: f64| {
+        let moved: Vec<Array> = inputs
+            .iter()
+            .enumerate()
+            .map(|(input, (data, shape))| {
+                let data = data
+                    .iter()
+                    .enumerate()
+                    .map(|(e, x)| match ind.is_none_or(|ind| ind == input) {
+                        true => x + h * direction(input, e),
+                        false => *x,
+                    })
+                    .collect();
+                (data, shape.clone())
+            })
+            .collect();
+        moved
+    };
+    let (output, shape) = value(inputs);
+
+    if !wrt.is_empty() {
+        let seed: Vec<f64> = (0..output.len()).map(|e| 1. + 0.5 * e as f64).collect();
+        let dot = |inputs: &[Array]| -> f64 {
+            let (output, _) = value(inputs);
+            output.iter().zip(&seed).map(|(y, dy)| y * dy).sum()
+        };
+        let grad_inputs = [inputs.to_vec(), vec![(seed.clone(), shape.clone())]].concat();
+        let gradients = exec(&f.grad(wrt).unwrap(), &grad_inputs);
+        for (&ind, (gradient, _)) in wrt.iter().zip(&gradients) {
+            let expected: Vec<f64> = (0..inputs[ind].0.len())
+                .map(|e| {
+                    let unit = |_: usize, other: usize| (other == e) as u8 as f64;
+                    let ahead = dot(&moved(Some(ind), &unit, H));
+                    let behind = dot(&moved(Some(ind), &unit, -H));
+                    (ahead - behind) / (2. * H)
+                })
+                .collect();
+            assert_close(gradient, &expected, &format!("gradient {ind} of [{src}]"));
+        }
+    }
+
+    let tangent = |input: usize, e: usize| ((e + 2 * input) % 3) as f64 - 0.7;
+    let tangents: Vec<Array> = inputs
+        .iter()
+        .enumerate()
+        .map(|(input, (data, shape))| {
+            let data = (0..data.len()).map(|e| tangent(input, e)).collect();
+            (data, shape.clone())
+        })
+        .collect();
+    let outputs = exec(&f.jvp().unwrap(), &[inputs.to_vec(), tangents].concat());
+    assert_close(&outputs[0].0, &output, &format!("primal of [{src}]"));
+    let (ahead, _) = value(&moved(None, &tangent, H));
+    let (behind, _) = value(&moved(None, &tangent, -H));
+    let expected: Vec<f64> = ahead
+        .iter()
+        .zip(&behind)
+        .map(|(ahead, behind)| (ahead - behind) / (2. * H))
+        .collect();
+    assert_close(&outputs[1].0, &expected, &format!("tangent of [{src}]"));
+}
+
+/// An array of `shape` of distinct values in `low..high`, none of them near 0
+fn array(shape: &[usize], low: f64, high: f64) -> Array {
+    let n: usize = shape.iter().product();
+    let data = (0..n)
+        .map(|e| {
+            // a permutation of the elements, so neighbors aren't ordered
+            let position = (e * 7 + 3) % n;
+            let value = low + (high - low) * (position as f64 + 0.5) / n as f64;
+            match value.abs() < 0.1 {
+                true => value + 0.2,
+                false => value,
+            }
+        })
+        .collect();
+    (data, shape.to_vec())
+}
+
+#[test]
+fn unary_derivatives() {
+    for src in [
+        "-ij~ij",
+        "/ij~ij",
+        "^ij~ij",
+        "exp ij~ij",
+        "abs ij~ij",
+        "tanh ij~ij",
+        "sigmoid ij~ij",
+        "sin ij~ij",
+        "cos ij~ij",
+        ">ij~ij",
+        "<ij~ij",
+        "ij~ji",
+    ] {
+        check_derivatives(src, &[array(&[2, 3], -2., 2.)], &[0]);
+    }
+    for src in ["$ij~ij", "log ij~ij", "sqrt ij~ij", "rsqrt ij~ij"] {
+        check_derivatives(src, &[array(&[2, 3], 0.5, 3.)], &[0]);
+    }
+}
+
+#[test]
+fn binary_derivatives() {
+    for (src, b_shape) in [
+        ("ij+ij~ij", &[2, 3][..]),
+        ("ij-ij~ij", &[2, 3]),
+        ("ij*ij~ij", &[2, 3]),
+        ("ij/ij~ij", &[2, 3]),
+        ("max ij ij~ij", &[2, 3]),
+        ("min ij ij~ij", &[2, 3]),
+        ("ij+j~ij", &[3]),
+        ("ij*i~ij", &[2]),
+        ("ij-ji~ij", &[3, 2]),
+    ] {
+        let (a, b) = (array(&[2, 3], -2., 2.), array(b_shape, 0.5, 3.));
+        check_derivatives(src, &[a, b], &[0, 1]);
+    }
+    for src in ["ij^ij~ij", "pow ij ij~ij"] {
+        let (a, b) = (array(&[2, 3], 0.5, 3.), array(&[2, 3], -2., 2.));
+        check_derivatives(src, &[a, b], &[0, 1]);
+    }
+}
+
+#[test]
+fn reduction_derivatives() {
+    let x = array(&[3, 4], -2., 2.);
+    for src in ["+ij~i", "+ij~j", ">ij~i", "<ij~j", "*ij~i", "*ij~j"] {
+        check_derivatives(src, &[x.clone()], &[0]);
+    }
+    // a zero in one row and two in another
+    let zeros = (vec![2., 0., -1.5, 0.5, 0., 0.], vec![2, 3]);
+    check_derivatives("*ij~i", &[zeros], &[0]);
+}
+
+#[test]
+fn chained_derivatives() {
+    let (x, w) = (array(&[2, 3], -2., 2.), array(&[3, 4], -1., 1.));
+    check_derivatives("m: ik*kj~ijk\na: +ijk~ij\nm.a", &[x.clone(), w], &[0, 1]);
+    check_derivatives("e: exp ij~ij\ns: +ij~i\nl: $i~i\ne.s.l", &[x.clone()], &[0]);
+    let (a, b) = (array(&[2, 3], -2.5, 1.), array(&[2, 3], 0.5, 3.));
+    check_derivatives(
+        "c: lt ij ij~ij\nw: where ij ij ij~ij\nc.w",
+        &[x.clone(), a, x, b],
+        &[2, 3],
+    );
+}
+
+#[test]
+fn forward_only_derivatives() {
+    // scans and affine indices only differentiate forward
+    check_derivatives("+ij~i\\j", &[array(&[2, 3], -2., 2.)], &[]);
+    check_derivatives(
+        "c: (i+k)*k~ik\na: +ik~i\nc.a",
+        &[
+            (vec![1., -2., 0.5, 3., -1.], vec![5]),
+            (vec![0.5, -1.], vec![2]),
+        ],
+        &[],
+    );
+}
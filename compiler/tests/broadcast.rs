@@ -0,0 +1,40 @@
+mod common;
+
+use common::{assert_close, exec, graph};
+use compiler::graph::Graph;
+use compiler::parser::Parser;
+
+#[test]
+fn broadcast_extents_of_one_or_the_index() {
+    let bias = graph("ij+i?j~ij");
+    let x = (vec![1., 2., 3., 4.], vec![2, 2]);
+    let outputs = exec(&bias, &[x.clone(), (vec![10., 20.], vec![1, 2])]);
+    assert_close(&outputs[0].0, &[11., 22., 13., 24.], "bias of extent 1");
+    let outputs = exec(&bias, &[x, (vec![10., 20., 30., 40.], vec![2, 2])]);
+    assert_close(&outputs[0].0, &[11., 22., 33., 44.], "bias of extent 2");
+
+    assert!(bias
+        .check_broadcasts(&[vec![Some(2), Some(2)], vec![Some(1), Some(2)]])
+        .is_ok());
+    assert!(bias
+        .check_broadcasts(&[vec![Some(3), Some(2)], vec![Some(4), Some(2)]])
+        .is_err());
+    // also where the index is reduced
+    assert!(graph("ij+i?j~j")
+        .check_broadcasts(&[vec![Some(3), Some(2)], vec![Some(4), Some(2)]])
+        .is_err());
+    assert!(graph("i?+i?~i")
+        .check_broadcasts(&[vec![Some(3)], vec![Some(4)]])
+        .is_err());
+}
+
+#[test]
+fn mismatched_broadcast_signatures_are_errors() {
+    let shapes = |src: &str| {
+        let (ast, expr_bank) = Parser::new(src).unwrap().parse().unwrap();
+        Graph::from_expr_bank(&expr_bank).set_leaf_shapes(&ast.2)
+    };
+    assert!(shapes("x: [3, 2]\ny: [1, 2]\nij+i?j~ij").is_ok());
+    assert!(shapes("x: [3, 2]\ny: [_, 2]\nij+i?j~ij").is_ok());
+    assert!(shapes("x: [3, 2]\ny: [4, 2]\nij+i?j~ij").is_err());
+}
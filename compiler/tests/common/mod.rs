@@ -0,0 +1,161 @@
+use std::ffi::c_void;
+use std::marker::PhantomData;
+
+use compiler::ast::DType;
+use compiler::backend::rust::RustBackend;
+use compiler::backend::{Build, Render};
+use compiler::block::{Statement, Type};
+use compiler::graph::Graph;
+use compiler::lowerer::Lowerer;
+use compiler::parser::Parser;
+use compiler::tempdir::TempDir;
+use libloading::{Library, Symbol};
+
+#[repr(C)]
+struct Tensor<'a> {
+    data: *const c_void,
+    shape: *const usize,
+    ndim: usize,
+    _marker: PhantomData<&'a [u8]>,
+}
+
+#[repr(C)]
+struct TensorMut<'a> {
+    data: *mut c_void,
+    shape: *const usize,
+    ndim: usize,
+    _marker: PhantomData<&'a mut [u8]>,
+}
+
+/// An input or output: its elements and shape
+pub type Array = (Vec<f64>, Vec<usize>);
+
+pub fn graph(src: &str) -> Graph {
+    let (ast, expr_bank) = Parser::new(src).unwrap().parse().unwrap();
+    let mut graph = Graph::from_expr_bank(&expr_bank);
+    graph.set_leaf_shapes(&ast.2).unwrap();
+    graph
+}
+
+/// A graph built for `f64` inputs and loaded, to run on any number of them
+pub struct Kernel {
+    library: Library,
+    dtypes: Vec<DType>, // of the outputs
+    _dir: TempDir,
+}
+
+impl Kernel {
+    pub fn build(graph: &Graph) -> Self {
+        let mut graph = graph.deepcopy();
+        graph
+            .set_leaf_dtypes(&vec![DType::F64; graph.leaves().len()])
+            .unwrap();
+        let program = Lowerer::new().lower(&graph);
+        let Statement::Function { args, .. } = &program.exec else {
+            panic!("Expected `Function` for executive function.")
+        };
+        let dtypes = args
+            .iter()
+            .filter_map(|arg| match arg.type_ {
+                Type::ArrayRef(true, dtype) | Type::Array(true, dtype) => Some(dtype),
+                _ => None,
+            })
+            .collect();
+
+        let dir = TempDir::new().unwrap();
+        let dylib_path = dir.path().join("graph.so");
+        RustBackend::build(&RustBackend::render(&program), &dylib_path).unwrap();
+        let library = unsafe { Library::new(&dylib_path) }.unwrap();
+        Kernel {
+            library,
+            dtypes,
+            _dir: dir,
+        }
+    }
+
+    pub fn run(&self, inputs: &[Array]) -> Vec<Array> {
+        let tensors: Vec<Tensor> = inputs
+            .iter()
+            .map(|(data, shape)| Tensor {
+                data: data.as_ptr() as *const c_void,
+                shape: shape.as_ptr(),
+                ndim: shape.len(),
+                _marker: PhantomData,
+            })
+            .collect();
+
+        unsafe {
+            let library = &self.library;
+            let rank: Symbol<extern "C" fn(usize) -> usize> = library.get(b"rank").unwrap();
+            let shape: Symbol<extern "C" fn(usize, *const Tensor, usize, usize, *mut usize)> =
+                library.get(b"shape").unwrap();
+            let f: Symbol<unsafe extern "C" fn(*const Tensor, usize, *mut TensorMut, usize)> =
+                library.get(b"f").unwrap();
+
+            let mut outputs: Vec<(Vec<u8>, Vec<usize>)> = (0..self.dtypes.len())
+                .map(|output| {
+                    let mut output_shape = vec![0; rank(output)];
+                    shape(
+                        output,
+                        tensors.as_ptr(),
+                        tensors.len(),
+                        output_shape.len(),
+                        output_shape.as_mut_ptr(),
+                    );
+                    let size = output_shape.iter().product::<usize>() * self.dtypes[output].size();
+                    (vec![0; size], output_shape)
+                })
+                .collect();
+            let mut outs: Vec<TensorMut> = outputs
+                .iter_mut()
+                .map(|(bytes, shape)| TensorMut {
+                    data: bytes.as_mut_ptr() as *mut c_void,
+                    shape: shape.as_ptr(),
+                    ndim: shape.len(),
+                    _marker: PhantomData,
+                })
+                .collect();
+            f(
+                tensors.as_ptr(),
+                tensors.len(),
+                outs.as_mut_ptr(),
+                outs.len(),
+            );
+
+            outputs
+                .into_iter()
+                .zip(&self.dtypes)
+                .map(|((bytes, shape), dtype)| {
+                    let values = bytes
+                        .chunks(dtype.size())
+                        .map(|bytes| match dtype {
+                            DType::F64 => f64::from_ne_bytes(bytes.try_into().unwrap()),
+                            DType::Bool => bytes[0] as f64,
+                            _ => panic!("Expected f64 or bool outputs, got {dtype}."),
+                        })
+                        .collect();
+                    (values, shape)
+                })
+                .collect()
+        }
+    }
+}
+
+/// Build `graph` for `f64` inputs and run it on `inputs`
+pub fn exec(graph: &Graph, inputs: &[Array]) -> Vec<Array> {
+    Kernel::build(graph).run(inputs)
+}
+
+pub fn assert_close(found: &[f64], expected: &[f64], what: &str) {
+    assert_eq!(
+        found.len(),
+        expected.len(),
+        "{what}: {found:?} != {expected:?}"
+    );
+    for (a, b) in found.iter().zip(expected) {
+        assert!(
+            (a - b).abs() <= 1e-4 * (1. + b.abs()),
+            "{what}: {found:?} != {expected:?}"
+        );
+    }
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::{assert_close, exec, graph};
+
+#[test]
+fn gather_reads_zero_out_of_range() {
+    let x = (vec![1., 2., 3., 4., 5., 6.], vec![3, 2]);
+    let positions = (vec![2., -1., 3., 0.], vec![4]);
+    let outputs = exec(&graph("gather ij k~kj"), &[x, positions]);
+    assert_close(
+        &outputs[0].0,
+        &[5., 6., 0., 0., 0., 0., 1., 2.],
+        "gather of rows [2, -1, 3, 0]",
+    );
+}
+
+#[test]
+fn scatter_skips_out_of_range() {
+    let bins = (vec![0.; 3], vec![3]);
+    let positions = (vec![0., 3., -1., 2., 0., 100.], vec![6]);
+    let outputs = exec(&graph("scatter i k~i"), &[bins, positions]);
+    assert_close(
+        &outputs[0].0,
+        &[2., 0., 1.],
+        "histogram of [0, 3, -1, 2, 0, 100]",
+    );
+
+    let x = (vec![1.; 4], vec![2, 2]);
+    let positions = (vec![1., -2., 2.], vec![3]);
+    let values = (vec![1., 2., 3., 4., 5., 6.], vec![3, 2]);
+    let outputs = exec(&graph("scatter ij k kj~ij"), &[x, positions, values]);
+    assert_close(
+        &outputs[0].0,
+        &[1., 1., 2., 3.],
+        "scatter of rows [1, -2, 2]",
+    );
+}
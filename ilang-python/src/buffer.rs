@@ -0,0 +1,156 @@
+use std::ffi::{c_int, c_void, CStr};
+
+use pyo3::exceptions::PyBufferError;
+use pyo3::ffi;
+use pyo3::prelude::*;
+
+use compiler::ast::DType;
+
+/// The memory of a buffer-protocol object (a NumPy array, `array.array`, `memoryview`, ...), read
+/// in place and held until dropped
+pub struct Buffer {
+    view: Box<ffi::Py_buffer>,
+    pub shape: Vec<usize>,
+    pub dtype: DType,
+}
+
+impl Buffer {
+    /// The buffer of `obj`, which must be C-contiguous with elements of a `DType`
+    pub fn get(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let mut view = Box::new(ffi::Py_buffer::new());
+        let flags = ffi::PyBUF_RECORDS_RO;
+        if unsafe { ffi::PyObject_GetBuffer(obj.as_ptr(), &mut *view, flags) } == -1 {
+            return Err(PyErr::fetch(obj.py()));
+        }
+        // held before validating, so it's released on errors too
+        let mut buffer = Buffer {
+            view,
+            shape: vec![],
+            dtype: DType::default(),
+        };
+
+        let view = &*buffer.view;
+        if unsafe { ffi::PyBuffer_IsContiguous(view, b'C' as _) } != 1 {
+            return Err(PyBufferError::new_err("Expected a C-contiguous buffer"));
+        }
+        let format = match view.format.is_null() {
+            true => "B".to_string(),
+            false => unsafe { CStr::from_ptr(view.format) }
+                .to_string_lossy()
+                .into_owned(),
+        };
+        buffer.dtype = format_dtype(&format, view.itemsize as usize).ok_or_else(|| {
+            PyBufferError::new_err(format!(
+                "Expected a buffer of f32, f64, f16, i32 or bool elements, got format [{format}] of {} bytes",
+                view.itemsize
+            ))
+        })?;
+        buffer.shape = (0..view.ndim as usize)
+            .map(|dim| unsafe { *view.shape.add(dim) } as usize)
+            .collect();
+        Ok(buffer)
+    }
+
+    pub fn ptr(&self) -> *const c_void {
+        self.view.buf
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        Python::with_gil(|_| unsafe { ffi::PyBuffer_Release(&mut *self.view) });
+    }
+}
+
+/// The `DType` of elements with the struct-module `format` and size `itemsize`
+fn format_dtype(format: &str, itemsize: usize) -> Option<DType> {
+    let little_endian = cfg!(target_endian = "little");
+    let code = match format.as_bytes() {
+        [b'@' | b'=', code] | [code] => *code,
+        [b'<', code] if little_endian => *code,
+        [b'>' | b'!', code] if !little_endian => *code,
+        _ => return None,
+    };
+    let dtype = match code {
+        b'f' => DType::F32,
+        b'd' => DType::F64,
+        b'e' => DType::F16,
+        b'i' | b'l' | b'q' => DType::I32,
+        b'?' => DType::Bool,
+        _ => return None,
+    };
+    (dtype.size() == itemsize).then_some(dtype)
+}
+
+/// The struct-module format of elements of `dtype`, if one exists
+fn dtype_format(dtype: DType) -> Option<&'static CStr> {
+    match dtype {
+        DType::F32 => Some(c"f"),
+        DType::F64 => Some(c"d"),
+        DType::F16 => Some(c"e"),
+        DType::I32 => Some(c"i"),
+        DType::Bool => Some(c"?"),
+        DType::BF16 => None,
+    }
+}
+
+/// Export `bytes`, C-contiguous elements of `dtype` shaped `shape`, through `view` for `obj`. The
+/// strides are freed by `release_view`.
+///
+/// # Safety
+///
+/// `view` must be valid, and `bytes` and `shape` must outlive `obj`.
+pub unsafe fn fill_view(
+    view: *mut ffi::Py_buffer,
+    flags: c_int,
+    bytes: &[u8],
+    shape: &[usize],
+    dtype: DType,
+    obj: Bound<'_, PyAny>,
+) -> PyResult<()> {
+    if view.is_null() {
+        return Err(PyBufferError::new_err("Expected a view to fill"));
+    }
+    let format = dtype_format(dtype)
+        .ok_or_else(|| PyBufferError::new_err(format!("{dtype} has no buffer format")))?;
+
+    let mut strides = vec![dtype.size() as ffi::Py_ssize_t; shape.len()];
+    for dim in (0..shape.len().saturating_sub(1)).rev() {
+        strides[dim] = strides[dim + 1] * shape[dim + 1] as ffi::Py_ssize_t;
+    }
+    let strides = Box::into_raw(strides.into_boxed_slice()) as *mut ffi::Py_ssize_t;
+
+    (*view).obj = obj.into_ptr();
+    (*view).buf = bytes.as_ptr() as *mut c_void;
+    (*view).len = bytes.len() as ffi::Py_ssize_t;
+    (*view).readonly = 0;
+    (*view).itemsize = dtype.size() as ffi::Py_ssize_t;
+    (*view).format = match flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+        true => format.as_ptr() as *mut _,
+        false => std::ptr::null_mut(),
+    };
+    (*view).ndim = shape.len() as c_int;
+    (*view).shape = match flags & ffi::PyBUF_ND == ffi::PyBUF_ND {
+        true => shape.as_ptr() as *mut ffi::Py_ssize_t,
+        false => std::ptr::null_mut(),
+    };
+    (*view).strides = match flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+        true => strides,
+        false => std::ptr::null_mut(),
+    };
+    (*view).suboffsets = std::ptr::null_mut();
+    (*view).internal = strides as *mut c_void;
+    Ok(())
+}
+
+/// Free the strides of a view filled by `fill_view`
+///
+/// # Safety
+///
+/// `view` must have been filled by `fill_view`, and not released before.
+pub unsafe fn release_view(view: *mut ffi::Py_buffer) {
+    let ndim = (*view).ndim as usize;
+    let strides =
+        std::ptr::slice_from_raw_parts_mut((*view).internal as *mut ffi::Py_ssize_t, ndim);
+    drop(Box::from_raw(strides));
+}
@@ -0,0 +1,274 @@
+use std::ffi::{c_void, CStr};
+use std::ops::{Deref, DerefMut};
+
+use pyo3::exceptions::{PyBufferError, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+
+use compiler::ast::DType;
+
+const DLPACK_CAPSULE: &CStr = c"dltensor";
+const USED_DLPACK_CAPSULE: &CStr = c"used_dltensor";
+
+/// `kDLCPU`, the only device tensors are read on
+pub const CPU: i32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DLDevice {
+    device_type: i32,
+    device_id: i32,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DLDataType {
+    code: u8,
+    bits: u8,
+    lanes: u16,
+}
+
+#[repr(C)]
+struct DLTensor {
+    data: *mut c_void,
+    device: DLDevice,
+    ndim: i32,
+    dtype: DLDataType,
+    shape: *mut i64,
+    strides: *mut i64,
+    byte_offset: u64,
+}
+
+#[repr(C)]
+struct DLManagedTensor {
+    dl_tensor: DLTensor,
+    manager_ctx: *mut c_void,
+    deleter: Option<unsafe extern "C" fn(*mut DLManagedTensor)>,
+}
+
+/// The DLPack type of elements of `dtype`: `kDLInt`, `kDLFloat`, `kDLBfloat` or `kDLBool`
+fn dl_dtype(dtype: DType) -> DLDataType {
+    let (code, bits) = match dtype {
+        DType::I32 => (0, 32),
+        DType::F32 => (2, 32),
+        DType::F64 => (2, 64),
+        DType::F16 => (2, 16),
+        DType::BF16 => (4, 16),
+        DType::Bool => (6, 8),
+    };
+    DLDataType {
+        code,
+        bits,
+        lanes: 1,
+    }
+}
+
+fn dtype(dl_dtype: DLDataType) -> Option<DType> {
+    let dtype = match (dl_dtype.code, dl_dtype.bits, dl_dtype.lanes) {
+        (0, 32, 1) => DType::I32,
+        (2, 32, 1) => DType::F32,
+        (2, 64, 1) => DType::F64,
+        (2, 16, 1) => DType::F16,
+        (4, 16, 1) => DType::BF16,
+        (6, 8, 1) => DType::Bool,
+        _ => return None,
+    };
+    Some(dtype)
+}
+
+/// A framework's tensor taken from a DLPack capsule, read in place and returned to it when
+/// dropped
+pub struct ManagedTensor {
+    managed: *mut DLManagedTensor,
+    pub shape: Vec<usize>,
+    pub dtype: DType,
+}
+
+impl std::fmt::Debug for ManagedTensor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ManagedTensor")
+            .field("shape", &self.shape)
+            .field("dtype", &self.dtype)
+            .finish()
+    }
+}
+
+// the producer gives up the tensor to the consumer, which may drop it on any thread, and reads
+// of it through `Tensor`s are borrow-checked like their own elements
+unsafe impl Send for ManagedTensor {}
+unsafe impl Sync for ManagedTensor {}
+
+impl ManagedTensor {
+    /// The tensor of `obj`, taken through its `__dlpack__`. It must be C-contiguous on the CPU
+    /// with elements of a `DType`.
+    pub fn from_dlpack(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let capsule = obj.call_method0("__dlpack__")?;
+        let managed = unsafe {
+            let managed = ffi::PyCapsule_GetPointer(capsule.as_ptr(), DLPACK_CAPSULE.as_ptr());
+            if managed.is_null() {
+                return Err(PyErr::fetch(obj.py()));
+            }
+            // consumed, so the capsule no longer deletes it
+            if ffi::PyCapsule_SetName(capsule.as_ptr(), USED_DLPACK_CAPSULE.as_ptr()) == -1 {
+                return Err(PyErr::fetch(obj.py()));
+            }
+            managed as *mut DLManagedTensor
+        };
+        // held before validating, so it's returned on errors too
+        let mut tensor = ManagedTensor {
+            managed,
+            shape: vec![],
+            dtype: DType::default(),
+        };
+
+        let dl_tensor = unsafe { &(*managed).dl_tensor };
+        if dl_tensor.device.device_type != CPU {
+            return Err(PyBufferError::new_err(format!(
+                "Expected a DLPack tensor on the CPU, got device type {}",
+                dl_tensor.device.device_type
+            )));
+        }
+        tensor.dtype = dtype(dl_tensor.dtype).ok_or_else(|| {
+            PyValueError::new_err(format!(
+                "Expected a DLPack tensor of f32, f64, f16, bf16, i32 or bool elements, got code {} of {} bits",
+                dl_tensor.dtype.code, dl_tensor.dtype.bits
+            ))
+        })?;
+        tensor.shape = (0..dl_tensor.ndim as usize)
+            .map(|dim| unsafe { *dl_tensor.shape.add(dim) } as usize)
+            .collect();
+
+        // no strides means C-contiguous; extents of 1 may have any stride
+        if !dl_tensor.strides.is_null() {
+            let mut stride = 1;
+            for dim in (0..tensor.shape.len()).rev() {
+                let found = unsafe { *dl_tensor.strides.add(dim) };
+                if tensor.shape[dim] != 1 && found != stride {
+                    return Err(PyBufferError::new_err(
+                        "Expected a C-contiguous DLPack tensor",
+                    ));
+                }
+                stride *= tensor.shape[dim] as i64;
+            }
+        }
+        Ok(tensor)
+    }
+
+    fn len(&self) -> usize {
+        self.shape.iter().product::<usize>() * self.dtype.size()
+    }
+
+    fn ptr(&self) -> *mut u8 {
+        let dl_tensor = unsafe { &(*self.managed).dl_tensor };
+        unsafe { (dl_tensor.data as *mut u8).add(dl_tensor.byte_offset as usize) }
+    }
+}
+
+impl Deref for ManagedTensor {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self.len() {
+            0 => &[],
+            len => unsafe { std::slice::from_raw_parts(self.ptr(), len) },
+        }
+    }
+}
+
+impl DerefMut for ManagedTensor {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self.len() {
+            0 => &mut [],
+            len => unsafe { std::slice::from_raw_parts_mut(self.ptr(), len) },
+        }
+    }
+}
+
+impl Drop for ManagedTensor {
+    fn drop(&mut self) {
+        unsafe {
+            if let Some(deleter) = (*self.managed).deleter {
+                deleter(self.managed);
+            }
+        }
+    }
+}
+
+/// What a capsule made by `to_dlpack` owns besides the tensor: `_obj`, keeping the elements
+/// alive, and the shape and strides
+struct Context {
+    _obj: Py<PyAny>,
+    shape: Vec<i64>,
+    strides: Vec<i64>,
+}
+
+/// A DLPack capsule of `bytes`, C-contiguous elements of `dtype` shaped `shape` owned by `obj`
+///
+/// # Safety
+///
+/// `bytes` must outlive `obj`.
+pub unsafe fn to_dlpack<'py>(
+    obj: Bound<'py, PyAny>,
+    bytes: &[u8],
+    shape: &[usize],
+    dtype: DType,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = obj.py();
+    let shape: Vec<i64> = shape.iter().map(|extent| *extent as i64).collect();
+    let mut strides = vec![1; shape.len()];
+    for dim in (0..shape.len().saturating_sub(1)).rev() {
+        strides[dim] = strides[dim + 1] * shape[dim + 1];
+    }
+    let mut context = Box::new(Context {
+        _obj: obj.unbind(),
+        shape,
+        strides,
+    });
+    let dl_tensor = DLTensor {
+        data: bytes.as_ptr() as *mut c_void,
+        device: DLDevice {
+            device_type: CPU,
+            device_id: 0,
+        },
+        ndim: context.shape.len() as i32,
+        dtype: dl_dtype(dtype),
+        shape: context.shape.as_mut_ptr(),
+        strides: context.strides.as_mut_ptr(),
+        byte_offset: 0,
+    };
+    let managed = Box::into_raw(Box::new(DLManagedTensor {
+        dl_tensor,
+        manager_ctx: Box::into_raw(context) as *mut c_void,
+        deleter: Some(delete),
+    }));
+
+    let capsule = ffi::PyCapsule_New(
+        managed as *mut c_void,
+        DLPACK_CAPSULE.as_ptr(),
+        Some(delete_unused),
+    );
+    if capsule.is_null() {
+        delete(managed);
+    }
+    Bound::from_owned_ptr_or_err(py, capsule)
+}
+
+/// The deleter of tensors made by `to_dlpack`, called by their consumer
+unsafe extern "C" fn delete(managed: *mut DLManagedTensor) {
+    let managed = Box::from_raw(managed);
+    let context = Box::from_raw(managed.manager_ctx as *mut Context);
+    Python::with_gil(|_| drop(context));
+}
+
+/// The destructor of capsules made by `to_dlpack`, which delete their tensor unless consumed
+unsafe extern "C" fn delete_unused(capsule: *mut ffi::PyObject) {
+    if ffi::PyCapsule_IsValid(capsule, DLPACK_CAPSULE.as_ptr()) == 1 {
+        let managed = ffi::PyCapsule_GetPointer(capsule, DLPACK_CAPSULE.as_ptr());
+        delete(managed as *mut DLManagedTensor);
+    }
+}
+
+/// Whether `obj` can be taken through `__dlpack__`
+pub fn has_dlpack(obj: &Bound<'_, PyAny>) -> bool {
+    obj.hasattr("__dlpack__").unwrap_or(false)
+}
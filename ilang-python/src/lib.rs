@@ -1,10 +1,28 @@
+mod buffer;
+mod dlpack;
+
+use std::collections::HashMap;
+use std::ffi::c_int;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
 use libloading::{Library, Symbol};
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::ffi;
 use pyo3::prelude::*;
-use pyo3::types::{PyList, PyTuple};
+use pyo3::types::{PyList, PyMemoryView, PyTuple};
+
+use buffer::Buffer;
+use dlpack::ManagedTensor;
 
 use compiler::{
-    backend::{rust::RustBackend, Build, Render},
+    ast::DType,
+    backend::{self, rust::RustBackend},
+    block::{Statement, Type},
+    cache::Cache,
     graph::Graph,
+    half::{bf16_to_f32, f16_to_f32, f32_to_bf16, f32_to_f16},
     lowerer::Lowerer,
     parser::Parser,
 };
@@ -12,34 +30,92 @@ use compiler::{
 #[derive(Debug)]
 #[repr(C)]
 pub struct Tensor<'a> {
-    pub data: *const f32,
+    pub data: *const std::ffi::c_void,
     pub shape: *const usize,
     pub ndim: usize,
-    pub _marker: std::marker::PhantomData<&'a [f32]>,
+    pub _marker: std::marker::PhantomData<&'a [u8]>,
 }
 
 #[derive(Debug)]
 #[repr(C)]
 pub struct TensorMut<'a> {
-    pub data: *mut f32,
+    pub data: *mut std::ffi::c_void,
     pub shape: *const usize,
     pub ndim: usize,
-    pub _marker: std::marker::PhantomData<&'a mut [f32]>,
+    pub _marker: std::marker::PhantomData<&'a mut [u8]>,
+}
+
+create_exception!(
+    ilang,
+    BuildError,
+    PyException,
+    "A kernel failed to build, with the compiler's `source`, `command`, exit `status` and `stderr` \
+     when it ran"
+);
+
+/// `e` as a `BuildError`, with the details of the compiler's run as attributes
+fn build_error(py: Python<'_>, e: backend::BuildError) -> PyErr {
+    let err = BuildError::new_err(e.to_string());
+    if let backend::BuildError::Compiler {
+        source,
+        command,
+        status,
+        stderr,
+    } = e
+    {
+        let value = err.value(py);
+        let set_attributes = || -> PyResult<()> {
+            value.setattr("source", source)?;
+            value.setattr("command", command)?;
+            value.setattr("status", status.code())?;
+            value.setattr("stderr", stderr)
+        };
+        if let Err(e) = set_attributes() {
+            return e;
+        }
+    }
+    err
 }
 
 #[pyclass]
 #[derive(Debug)]
 struct Component {
     graph: Graph,
+    kernels: Mutex<Kernels>,
+}
+
+/// The kernels of a component by input dtypes and whether they accumulate wide
+type Kernels = HashMap<(Vec<DType>, bool), Arc<Kernel>>;
+
+/// A component compiled for some input dtypes, loaded once and run on every call
+#[derive(Debug)]
+struct Kernel {
+    library: Library,
+    dtypes: Vec<DType>, // of the outputs
+}
+
+impl From<Graph> for Component {
+    fn from(graph: Graph) -> Self {
+        Component {
+            graph,
+            kernels: Mutex::new(HashMap::new()),
+        }
+    }
 }
 
 #[pymethods]
 impl Component {
     #[new]
     fn new(src: String) -> PyResult<Self> {
-        let (_ast, expr_bank) = Parser::new(&src).unwrap().parse().unwrap();
-        let graph = Graph::from_expr_bank(&expr_bank);
-        Ok(Component { graph })
+        let (ast, expr_bank) = Parser::new(&src)
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?
+            .parse()
+            .map_err(|e| PyErr::new::<pyo3::exceptions::PyValueError, _>(e.to_string()))?;
+        let mut graph = Graph::from_expr_bank(&expr_bank);
+        graph
+            .set_leaf_shapes(&ast.2)
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        Ok(Component::from(graph))
     }
 
     fn __str__(&self) -> PyResult<String> {
@@ -48,9 +124,7 @@ impl Component {
 
     #[pyo3(name = "chain")]
     fn chain(&self, other: &Component) -> PyResult<Component> {
-        Ok(Component {
-            graph: self.graph.chain(&other.graph),
-        })
+        Ok(Component::from(self.graph.chain(&other.graph)))
     }
 
     #[pyo3(name = "__or__")]
@@ -60,9 +134,7 @@ impl Component {
 
     #[pyo3(name = "compose")]
     fn compose(&self, other: &Component) -> PyResult<Component> {
-        Ok(Component {
-            graph: self.graph.compose(&other.graph),
-        })
+        Ok(Component::from(self.graph.compose(&other.graph)))
     }
 
     #[pyo3(name = "__call__")]
@@ -72,9 +144,7 @@ impl Component {
 
     #[pyo3(name = "fanout")]
     fn fanout(&self, other: &Component) -> PyResult<Component> {
-        Ok(Component {
-            graph: self.graph.fanout(&other.graph),
-        })
+        Ok(Component::from(self.graph.fanout(&other.graph)))
     }
 
     #[pyo3(name = "__and__")]
@@ -82,60 +152,308 @@ impl Component {
         self.fanout(other)
     }
 
-    #[pyo3(signature = (*args))]
-    fn exec(&self, args: &Bound<'_, PyTuple>) -> PyResult<PyTensor> {
-        let mut tensors: Vec<PyTensor> = args.extract()?;
+    /// The gradient of the outputs with respect to the inputs numbered `wrt`, taking the inputs
+    /// followed by the gradient of each output
+    #[pyo3(signature = (*wrt))]
+    fn grad(&self, wrt: Vec<usize>) -> PyResult<Component> {
+        let graph = self
+            .graph
+            .grad(&wrt)
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        Ok(Component::from(graph))
+    }
+
+    /// The Jacobian-vector product, taking the inputs followed by a tangent for each, and
+    /// returning the outputs followed by the tangent of each
+    fn jvp(&self) -> PyResult<Component> {
+        let graph = self
+            .graph
+            .jvp()
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        Ok(Component::from(graph))
+    }
+
+    /// Run the component on `args`, `Tensor`s, C-contiguous DLPack tensors like PyTorch's or
+    /// buffer-protocol objects like NumPy arrays, read in place, returning a `Tensor` for each of
+    /// its outputs, or the one `Tensor` when it has a single output
+    #[pyo3(signature = (*args, wide_accumulation=false))]
+    fn exec(
+        &self,
+        py: Python<'_>,
+        args: &Bound<'_, PyTuple>,
+        wide_accumulation: bool,
+    ) -> PyResult<PyObject> {
+        let inputs = args
+            .iter()
+            .map(|arg| Input::extract(&arg))
+            .collect::<PyResult<Vec<_>>>()?;
+
+        let n_leaves = self.graph.leaves().len();
+        if inputs.len() != n_leaves {
+            return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                "Expected {n_leaves} input tensors, got {}",
+                inputs.len()
+            )));
+        }
+        // kernels index static dimensions without bounds checks
+        for (ind, (input, shape)) in inputs.iter().zip(self.graph.leaf_shapes()).enumerate() {
+            let matches =
+                input.shape().len() == shape.len()
+                    && input.shape().iter().zip(&shape).all(|(extent, expected)| {
+                        expected.is_none_or(|expected| expected == *extent)
+                    });
+            if !matches {
+                let signature = shape
+                    .iter()
+                    .map(|extent| extent.map_or("_".to_string(), |extent| extent.to_string()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Expected input {ind} of shape [{signature}], got {:?}",
+                    input.shape()
+                )));
+            }
+        }
+        // nor broadcast dimensions
+        let shapes: Vec<Vec<Option<usize>>> = inputs
+            .iter()
+            .map(|input| input.shape().iter().map(|extent| Some(*extent)).collect())
+            .collect();
+        self.graph
+            .check_broadcasts(&shapes)
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        let dtypes = inputs.iter().map(Input::dtype).collect();
+        let kernel = self.kernel(py, dtypes, wide_accumulation)?;
 
         // convert to backend `Tensor`s
-        let tensors = tensors
+        let tensors = inputs
             .iter()
-            .map(|tensor| Tensor {
-                data: tensor.data.as_ptr(),
-                shape: tensor.shape.as_ptr(),
-                ndim: tensor.shape.len(),
+            .map(|input| Tensor {
+                data: input.data(),
+                shape: input.shape().as_ptr(),
+                ndim: input.shape().len(),
                 _marker: std::marker::PhantomData,
             })
             .collect::<Vec<_>>();
 
-        let block = Lowerer::new().lower(&self.graph);
-        let dylib_path = RustBackend::build(&RustBackend::render(&block)).unwrap();
+        let mut outputs = unsafe {
+            let dylib = &kernel.library;
+            let n_outputs: Symbol<extern "C" fn() -> usize> = dylib.get(b"n_outputs").unwrap();
+            let rank: Symbol<extern "C" fn(usize) -> usize> = dylib.get(b"rank").unwrap();
 
-        unsafe {
-            let dylib = Library::new(&dylib_path).unwrap();
-            let rank: Symbol<extern "C" fn() -> usize> = dylib.get(b"rank").unwrap();
-
-            let fshape: Symbol<extern "C" fn(*const Tensor, usize, usize, *mut usize)> =
+            let fshape: Symbol<extern "C" fn(usize, *const Tensor, usize, usize, *mut usize)> =
                 dylib.get(b"shape").unwrap();
-            let f: Symbol<unsafe extern "C" fn(*const Tensor, usize, *mut TensorMut)> =
+            let f: Symbol<unsafe extern "C" fn(*const Tensor, usize, *mut TensorMut, usize)> =
                 dylib.get(b"f").unwrap();
 
-            let mut shape = vec![0; rank()];
-            fshape(tensors.as_ptr(), tensors.len(), rank(), shape.as_mut_ptr());
+            let mut outputs = (0..n_outputs())
+                .zip(kernel.dtypes.iter().copied())
+                .map(|(output, dtype)| {
+                    let mut shape = vec![0; rank(output)];
+                    fshape(
+                        output,
+                        tensors.as_ptr(),
+                        tensors.len(),
+                        shape.len(),
+                        shape.as_mut_ptr(),
+                    );
+                    PyTensor {
+                        bytes: Storage::Owned(vec![
+                            0u8;
+                            shape.iter().product::<usize>() * dtype.size()
+                        ]),
+                        shape,
+                        dtype,
+                    }
+                })
+                .collect::<Vec<_>>();
+            let mut outs = outputs
+                .iter_mut()
+                .map(|output| TensorMut {
+                    data: output.bytes.as_mut_ptr() as *mut _,
+                    shape: output.shape.as_ptr(),
+                    ndim: output.shape.len(),
+                    _marker: std::marker::PhantomData,
+                })
+                .collect::<Vec<_>>();
+
+            f(
+                tensors.as_ptr(),
+                tensors.len(),
+                outs.as_mut_ptr(),
+                outs.len(),
+            );
+
+            outputs
+        };
+
+        match outputs.len() {
+            1 => Ok(Py::new(py, outputs.remove(0))?.into_any()),
+            _ => {
+                let outputs = outputs
+                    .into_iter()
+                    .map(|output| Py::new(py, output))
+                    .collect::<PyResult<Vec<_>>>()?;
+                Ok(PyTuple::new(py, outputs)?.into_any().unbind())
+            }
+        }
+    }
+}
 
-            let mut data = vec![0f32; shape.iter().product()];
-            let mut out = TensorMut {
-                data: data.as_mut_ptr(),
-                shape: shape.as_ptr(),
-                ndim: shape.len(),
-                _marker: std::marker::PhantomData,
-            };
+impl Component {
+    /// The kernel for inputs of `dtypes`, lowered, built and loaded on the first call with them.
+    /// Builds are cached on disk too, so other components and processes reuse them.
+    fn kernel(
+        &self,
+        py: Python<'_>,
+        dtypes: Vec<DType>,
+        wide_accumulation: bool,
+    ) -> PyResult<Arc<Kernel>> {
+        let key = (dtypes, wide_accumulation);
+        if let Some(kernel) = self.kernels.lock().unwrap().get(&key) {
+            return Ok(Arc::clone(kernel));
+        }
 
-            f(tensors.as_ptr(), tensors.len(), &mut out);
+        // specialize the graph to the input dtypes
+        let mut graph = self.graph.deepcopy();
+        graph
+            .set_leaf_dtypes(&key.0)
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+
+        // built without the GIL or the lock on `kernels`, so other threads run meanwhile; threads
+        // building the same kernel wait on the cache for the first
+        let built = py.allow_threads(|| -> Result<Kernel, backend::BuildError> {
+            let block = Lowerer::new()
+                .wide_accumulation(wide_accumulation)
+                .lower(&graph);
+            let library = Cache::from_env().build::<RustBackend, _>(&block, |dylib_path| {
+                unsafe { Library::new(dylib_path) }
+                    .map_err(|e| backend::BuildError::Io(std::io::Error::other(e)))
+            })?;
+            Ok(Kernel {
+                library,
+                dtypes: output_dtypes(&block.exec),
+            })
+        });
+        let kernel = Arc::new(built.map_err(|e| build_error(py, e))?);
+        let mut kernels = self.kernels.lock().unwrap();
+        Ok(Arc::clone(kernels.entry(key).or_insert(kernel)))
+    }
+}
+
+/// An argument to `exec`, whose elements are read in place
+enum Input<'py> {
+    Tensor(PyRef<'py, PyTensor>),
+    DLPack(ManagedTensor),
+    Buffer(Buffer),
+}
+
+impl<'py> Input<'py> {
+    fn extract(arg: &Bound<'py, PyAny>) -> PyResult<Self> {
+        match arg.downcast::<PyTensor>() {
+            Ok(tensor) => Ok(Input::Tensor(tensor.borrow())),
+            Err(_) if dlpack::has_dlpack(arg) => ManagedTensor::from_dlpack(arg).map(Input::DLPack),
+            Err(_) => Buffer::get(arg).map(Input::Buffer),
+        }
+    }
 
-            std::fs::remove_file(dylib_path).unwrap();
+    fn data(&self) -> *const std::ffi::c_void {
+        match self {
+            Input::Tensor(tensor) => tensor.bytes.as_ptr() as *const _,
+            Input::DLPack(managed) => managed.as_ptr() as *const _,
+            Input::Buffer(buffer) => buffer.ptr(),
+        }
+    }
+
+    fn shape(&self) -> &[usize] {
+        match self {
+            Input::Tensor(tensor) => &tensor.shape,
+            Input::DLPack(managed) => &managed.shape,
+            Input::Buffer(buffer) => &buffer.shape,
+        }
+    }
 
-            Ok(PyTensor { data, shape })
+    fn dtype(&self) -> DType {
+        match self {
+            Input::Tensor(tensor) => tensor.dtype,
+            Input::DLPack(managed) => managed.dtype,
+            Input::Buffer(buffer) => buffer.dtype,
+        }
+    }
+}
+
+/// The dtype of each output array, the mutable array arguments of the executive function
+fn output_dtypes(exec: &Statement) -> Vec<DType> {
+    let Statement::Function { args, .. } = exec else {
+        panic!("Expected `Function` for executive function.")
+    };
+    args.iter()
+        .filter_map(|arg| match arg.type_ {
+            Type::ArrayRef(true, dtype) | Type::Array(true, dtype) => Some(dtype),
+            _ => None,
+        })
+        .collect()
+}
+
+/// The elements of a `Tensor`, its own or a framework's taken through DLPack
+#[derive(Debug)]
+enum Storage {
+    Owned(Vec<u8>),
+    DLPack(ManagedTensor),
+}
+
+impl Deref for Storage {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Storage::Owned(bytes) => bytes,
+            Storage::DLPack(managed) => managed,
+        }
+    }
+}
+
+impl DerefMut for Storage {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Storage::Owned(bytes) => bytes,
+            Storage::DLPack(managed) => managed,
         }
     }
 }
 
 #[pyclass(name = "Tensor")]
-#[derive(Debug, FromPyObject)]
+#[derive(Debug)]
 struct PyTensor {
-    #[pyo3(get)]
-    data: Vec<f32>,
+    bytes: Storage, // elements in the native layout of `dtype`
     #[pyo3(get)]
     shape: Vec<usize>,
+    dtype: DType,
+}
+
+/// Append `value` to `bytes` as an element of `dtype`
+fn encode(value: f64, dtype: DType, bytes: &mut Vec<u8>) {
+    match dtype {
+        DType::F32 => bytes.extend((value as f32).to_ne_bytes()),
+        DType::F64 => bytes.extend(value.to_ne_bytes()),
+        DType::F16 => bytes.extend(f32_to_f16(value as f32).to_ne_bytes()),
+        DType::BF16 => bytes.extend(f32_to_bf16(value as f32).to_ne_bytes()),
+        DType::I32 => bytes.extend((value as i32).to_ne_bytes()),
+        DType::Bool => bytes.push((value != 0.) as u8),
+    }
+}
+
+/// Read the element of `dtype` at the start of `bytes`
+fn decode(bytes: &[u8], dtype: DType) -> f64 {
+    let array = |n: usize| &bytes[..n];
+    match dtype {
+        DType::F32 => f32::from_ne_bytes(array(4).try_into().unwrap()) as f64,
+        DType::F64 => f64::from_ne_bytes(array(8).try_into().unwrap()),
+        DType::F16 => f16_to_f32(u16::from_ne_bytes(array(2).try_into().unwrap())) as f64,
+        DType::BF16 => bf16_to_f32(u16::from_ne_bytes(array(2).try_into().unwrap())) as f64,
+        DType::I32 => i32::from_ne_bytes(array(4).try_into().unwrap()) as f64,
+        DType::Bool => (bytes[0] != 0) as u8 as f64,
+    }
 }
 
 fn infer_shape(list: &Bound<'_, PyList>) -> PyResult<Vec<usize>> {
@@ -163,7 +481,7 @@ fn validate_and_flatten(
     list: &Bound<'_, PyList>,
     shape: &[usize],
     dim: usize,
-    data: &mut Vec<f32>,
+    data: &mut Vec<f64>,
 ) -> PyResult<()> {
     if dim >= shape.len() {
         return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(
@@ -197,8 +515,33 @@ fn validate_and_flatten(
 
 #[pymethods]
 impl PyTensor {
+    /// A tensor of nested lists of `elements`, `f32` by default, or a copy of the elements of a
+    /// C-contiguous buffer-protocol object, of its own dtype
     #[new]
-    fn new(elements: &Bound<'_, PyList>) -> PyResult<Self> {
+    #[pyo3(signature = (elements, dtype=None))]
+    fn new(elements: &Bound<'_, PyAny>, dtype: Option<&str>) -> PyResult<Self> {
+        let dtype = dtype
+            .map(str::parse::<DType>)
+            .transpose()
+            .map_err(PyErr::new::<pyo3::exceptions::PyValueError, _>)?;
+        let Ok(elements) = elements.downcast::<PyList>() else {
+            let buffer = Buffer::get(elements)?;
+            if dtype.is_some_and(|dtype| dtype != buffer.dtype) {
+                return Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(format!(
+                    "Expected dtype {} of the buffer, got {}",
+                    buffer.dtype,
+                    dtype.unwrap()
+                )));
+            }
+            let size = buffer.shape.iter().product::<usize>() * buffer.dtype.size();
+            let bytes = unsafe { std::slice::from_raw_parts(buffer.ptr() as *const u8, size) };
+            return Ok(Self {
+                bytes: Storage::Owned(bytes.to_vec()),
+                shape: buffer.shape.clone(),
+                dtype: buffer.dtype,
+            });
+        };
+        let dtype = dtype.unwrap_or_default();
         let shape = infer_shape(elements)?;
         let mut data = Vec::new();
         validate_and_flatten(elements, &shape, 0, &mut data)?;
@@ -213,20 +556,143 @@ impl PyTensor {
             )));
         }
 
-        Ok(Self { data, shape })
+        let mut bytes = Vec::with_capacity(data.len() * dtype.size());
+        for value in data {
+            encode(value, dtype, &mut bytes);
+        }
+
+        Ok(Self {
+            bytes: Storage::Owned(bytes),
+            shape,
+            dtype,
+        })
+    }
+
+    /// A tensor viewing the elements of a C-contiguous DLPack tensor on the CPU, like PyTorch's
+    /// or JAX's, in place
+    #[staticmethod]
+    fn from_dlpack(obj: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let managed = ManagedTensor::from_dlpack(obj)?;
+        Ok(Self {
+            shape: managed.shape.clone(),
+            dtype: managed.dtype,
+            bytes: Storage::DLPack(managed),
+        })
+    }
+
+    /// A DLPack capsule viewing the elements in place, for `torch.from_dlpack` and the like. Only
+    /// the CPU and the current stream exist, so the arguments are accepted but not needed.
+    #[pyo3(signature = (stream=None, max_version=None, dl_device=None, copy=None))]
+    fn __dlpack__<'py>(
+        slf: &Bound<'py, Self>,
+        stream: Option<&Bound<'py, PyAny>>,
+        max_version: Option<&Bound<'py, PyAny>>,
+        dl_device: Option<&Bound<'py, PyAny>>,
+        copy: Option<bool>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let _ = (stream, max_version, dl_device);
+        if copy == Some(true) {
+            let tensor = slf.borrow();
+            let copied = Bound::new(
+                slf.py(),
+                PyTensor {
+                    bytes: Storage::Owned(tensor.bytes.to_vec()),
+                    shape: tensor.shape.clone(),
+                    dtype: tensor.dtype,
+                },
+            )?;
+            return Self::__dlpack__(&copied, None, None, None, None);
+        }
+        let tensor = slf.borrow();
+        unsafe {
+            dlpack::to_dlpack(
+                slf.clone().into_any(),
+                &tensor.bytes,
+                &tensor.shape,
+                tensor.dtype,
+            )
+        }
+    }
+
+    fn __dlpack_device__(&self) -> (i32, i32) {
+        (dlpack::CPU, 0)
     }
 
-    fn __str__(&self) -> PyResult<String> {
+    #[getter]
+    fn data<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let values = self
+            .bytes
+            .chunks(self.dtype.size())
+            .map(|bytes| decode(bytes, self.dtype));
+        match self.dtype {
+            DType::Bool => PyList::new(py, values.map(|v| v != 0.)),
+            DType::I32 => PyList::new(py, values.map(|v| v as i64)),
+            _ => PyList::new(py, values),
+        }
+    }
+
+    #[getter]
+    fn dtype(&self) -> String {
+        self.dtype.to_string()
+    }
+
+    /// A NumPy array viewing the elements in place
+    fn to_numpy<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        // through a `memoryview`, which NumPy can't mistake for an object with `__array__`
+        let view = PyMemoryView::from(slf.as_any())?;
+        let numpy = slf.py().import("numpy")?;
+        numpy.call_method1("asarray", (view,))
+    }
+
+    #[pyo3(signature = (dtype=None, copy=None))]
+    fn __array__<'py>(
+        slf: &Bound<'py, Self>,
+        dtype: Option<&Bound<'py, PyAny>>,
+        copy: Option<bool>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let array = Self::to_numpy(slf)?;
+        match (dtype, copy) {
+            (Some(dtype), _) => array.call_method1("astype", (dtype,)),
+            (None, Some(true)) => array.call_method0("copy"),
+            (None, _) => Ok(array),
+        }
+    }
+
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        let tensor = slf.borrow();
+        let (bytes, shape) = (&tensor.bytes, &tensor.shape);
+        buffer::fill_view(
+            view,
+            flags,
+            bytes,
+            shape,
+            tensor.dtype,
+            slf.clone().into_any(),
+        )
+    }
+
+    unsafe fn __releasebuffer__(&self, view: *mut ffi::Py_buffer) {
+        buffer::release_view(view);
+    }
+
+    fn __str__(&self, py: Python<'_>) -> PyResult<String> {
         Ok(format!(
-            "Tensor(shape={:?}, data={:?})",
-            self.shape, self.data
+            "Tensor(shape={:?}, dtype={}, data={})",
+            self.shape,
+            self.dtype,
+            self.data(py)?
         ))
     }
 }
 
 #[pymodule]
-fn ilang(_py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
+fn ilang(py: Python<'_>, m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<PyTensor>()?;
     m.add_class::<Component>()?;
+    m.add("BuildError", py.get_type::<BuildError>())?;
     Ok(())
 }